@@ -1,19 +1,26 @@
-use imgcmp_lib;
 use std::env;
 use anyhow::Context;
 use image::GenericImageView;
 
 fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let config = match args.iter().position(|arg| arg == "--config") {
+        Some(index) => {
+            let path = args.get(index + 1).context("--config should be followed by the path of a TOML file")?;
+            let config = imgcmp_lib::Config::from_toml_path(path)?;
+            args.drain(index..index + 2);
+            config
+        },
+        None => imgcmp_lib::Config {
+            dct_dimension : 32,
+            dct_reduced_dimension : 8,
+            allowed_distance : 3,
+            ..imgcmp_lib::Config::default()
+        }
+    };
     let img1 = read_image(&args[1])?;
     let img2 = read_image(&args[2])?;
 
-    let config = imgcmp_lib::Config {
-        dct_dimension : 32,
-        dct_reduced_dimension : 8,
-        allowed_distance : 3
-    };
-
     let are_same = imgcmp_lib::compare_images(&img1, &img2, config)?;
     if are_same {
         println!("Pictures are the same");
@@ -21,7 +28,7 @@ fn main() -> anyhow::Result<()> {
     else {
         println!("Pictures are different");
     }
-    return Ok(());
+    Ok(())
 }
 
 fn read_image(path : &str) -> anyhow::Result<imgcmp_lib::Image> {
@@ -32,5 +39,6 @@ fn read_image(path : &str) -> anyhow::Result<imgcmp_lib::Image> {
 
     let width = decoded_image.width();
     let channel_count = decoded_image.color().channel_count();
-    imgcmp_lib::Image::from(&decoded_image.into_bytes(),width, channel_count)
+    let image = imgcmp_lib::Image::from(&decoded_image.into_bytes(),width, channel_count)?;
+    Ok(image)
 }
@@ -0,0 +1,14 @@
+#![no_main]
+
+use imgcmp_lib::Image;
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary buffers and dimensions should either create a consistent image or return an error, never panic
+fuzz_target!(|input : (Vec<u8>, u32, u8)| {
+    let (raw_image, width, channels_per_pixel) = input;
+    if let Ok(image) = Image::from(&raw_image, width, channels_per_pixel) {
+        assert_eq!(image.pixels_len(), image.get_width() as usize * image.get_height() as usize);
+        assert_eq!(image.to_bytes(), raw_image);
+        image.scale(1, 1).expect("images created from buffers can be scaled");
+    }
+});
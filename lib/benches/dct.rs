@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use imgcmp_lib::{bench, core, Image};
+use std::hint::black_box;
+
+fn dct_benchmark(c : &mut Criterion) {
+    let dim = 32;
+    let pixels : Vec<u8> = (0..dim * dim).map(|i| (i * 7 % 256) as u8).collect();
+    let image = Image::from(&pixels, dim as u32, 1).unwrap();
+    let float_pixels : Vec<f32> = pixels.iter().map(|p| *p as f32).collect();
+    let dct_basis = bench::calc_dct_basis(dim as u32);
+
+    c.bench_function("matrix dct 32x32", |b| {
+        b.iter(|| bench::calc_dct_coefficients(black_box(&image), &dct_basis))
+    });
+    c.bench_function("slice dct 32x32", |b| {
+        b.iter(|| core::dct_2d(black_box(&float_pixels), dim))
+    });
+}
+
+fn large_dct_benchmark(c : &mut Criterion) {
+    let dim = 64;
+    let pixels : Vec<u8> = (0..dim * dim).map(|i| (i * 7 % 256) as u8).collect();
+    let image = Image::from(&pixels, dim as u32, 1).unwrap();
+    let dct_basis = bench::calc_dct_basis(dim as u32);
+
+    c.bench_function("matrix dct 64x64", |b| {
+        b.iter(|| bench::calc_dct_coefficients(black_box(&image), &dct_basis))
+    });
+    #[cfg(feature = "rayon")]
+    c.bench_function("parallel matrix dct 64x64", |b| {
+        b.iter(|| bench::calc_dct_coefficients_parallel(black_box(&image), &dct_basis))
+    });
+}
+
+criterion_group!(benches, dct_benchmark, large_dct_benchmark);
+criterion_main!(benches);
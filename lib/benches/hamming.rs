@@ -0,0 +1,18 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use imgcmp_lib::core;
+use std::hint::black_box;
+
+fn hamming_benchmark(c : &mut Criterion) {
+    let candidates : Vec<u64> = (0..100_000_u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15)).collect();
+    let query = 0x0123456789ABCDEF;
+
+    c.bench_function("scalar hamming 100000", |b| {
+        b.iter(|| black_box(&candidates).iter().map(|candidate| core::hamming_distance(query, *candidate)).collect::<Vec<u8>>())
+    });
+    c.bench_function("batch hamming 100000", |b| {
+        b.iter(|| core::hamming_distances(query, black_box(&candidates)))
+    });
+}
+
+criterion_group!(benches, hamming_benchmark);
+criterion_main!(benches);
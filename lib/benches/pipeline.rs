@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use imgcmp_lib::{bench, compare_images, Config, Image, PerceptualHasher};
+use image::GenericImageView;
+use std::hint::black_box;
+
+fn load_cat() -> Image {
+    let decoded_image = image::open("../assets/cat.jpg").unwrap();
+    Image::from(decoded_image.as_bytes(), decoded_image.width(), decoded_image.color().channel_count()).unwrap()
+}
+
+fn dct_benchmark(c : &mut Criterion) {
+    let dim = Config::default().dct_dimension;
+    let shrank_image = load_cat().scale(dim, dim).unwrap().into_grayscale();
+    let dct_basis = bench::calc_dct_basis(dim);
+
+    c.bench_function("dct basis 32x32", |b| {
+        b.iter(|| bench::calc_dct_basis(black_box(dim)))
+    });
+    c.bench_function("dct coefficients of cat.jpg", |b| {
+        b.iter(|| bench::calc_dct_coefficients(black_box(&shrank_image), &dct_basis))
+    });
+}
+
+fn hash_benchmark(c : &mut Criterion) {
+    let image = load_cat();
+    let hasher = PerceptualHasher::new(Config::default());
+
+    c.bench_function("hash cat.jpg", |b| {
+        b.iter(|| hasher.hash(black_box(&image)).unwrap())
+    });
+    c.bench_function("compare cat.jpg", |b| {
+        b.iter(|| compare_images(black_box(&image), black_box(&image.grayscale()), Config::default()).unwrap())
+    });
+}
+
+fn batch_hash_benchmark(c : &mut Criterion) {
+    let images = vec!(load_cat(); 16);
+    let hasher = PerceptualHasher::new(Config::default());
+
+    c.bench_function("hash 16 copies of cat.jpg", |b| {
+        b.iter(|| black_box(&images).iter().map(|image| hasher.hash(image).unwrap()).collect::<Vec<_>>())
+    });
+}
+
+criterion_group!(benches, dct_benchmark, hash_benchmark, batch_hash_benchmark);
+criterion_main!(benches);
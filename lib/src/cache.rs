@@ -0,0 +1,149 @@
+use crate::{ImageHash, ImgcmpError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Hash of a file, along with the metadata used for detecting whether the file changed since it was hashed
+#[derive (Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheRecord {
+    path : PathBuf,
+    mtime_nanos : u128,
+    size : u64,
+    hash : u64
+}
+
+/// Hashes of files keyed by their path, so that unchanged files are not hashed again on repeated scans.
+/// A file is considered unchanged when both its modification time and its size are the cached ones.
+#[derive (Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashCache {
+    records : HashMap<PathBuf, CacheRecord>
+}
+
+impl HashCache {
+    pub fn new() -> HashCache {
+        HashCache::default()
+    }
+
+    /// Loads a cache saved by `save`.
+    pub fn load(path : &Path) -> Result<HashCache> {
+        HashCache::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Saves the cache to the passed file, overwriting it.
+    pub fn save(&self, path : &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Deserializes a cache from the JSON created by `to_json`.
+    pub fn from_json(json : &str) -> Result<HashCache> {
+        let records : Vec<CacheRecord> = serde_json::from_str(json).
+            map_err(|error| ImgcmpError::InvalidCache(error.to_string()))?;
+        let records = records.into_iter().map(|record| (record.path.clone(), record)).collect();
+        Ok(HashCache { records })
+    }
+
+    /// Serializes the cache to JSON, as an array of `{path, mtime_nanos, size, hash}` records.
+    pub fn to_json(&self) -> Result<String> {
+        let mut records : Vec<&CacheRecord> = self.records.values().collect();
+        records.sort_by(|left, right| left.path.cmp(&right.path));
+        serde_json::to_string(&records).map_err(|error| ImgcmpError::InvalidCache(error.to_string()))
+    }
+
+    /// Number of cached files
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the cached hash of the passed file if it is unchanged, otherwise hashes it with
+    /// `hash_file` and caches the new hash.
+    pub fn get_or_hash<F>(&mut self, file : &Path, hash_file : F) -> Result<ImageHash> where F: FnOnce(&Path) -> Result<ImageHash> {
+        let metadata = fs::metadata(file)?;
+        let mtime_nanos = metadata.modified()?.duration_since(UNIX_EPOCH).
+            map_err(|error| ImgcmpError::Io(error.to_string()))?.
+            as_nanos();
+        let size = metadata.len();
+
+        if let Some(record) = self.records.get(file) {
+            if record.mtime_nanos == mtime_nanos && record.size == size {
+                return Ok(ImageHash(record.hash));
+            }
+        }
+
+        let hash = hash_file(file)?;
+        let record = CacheRecord { path : file.to_path_buf(), mtime_nanos, size, hash : hash.0 };
+        self.records.insert(file.to_path_buf(), record);
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    fn temp_file(name : &str, contents : &[u8]) -> anyhow::Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!("imgcmp-cache-{}-{}", std::process::id(), name));
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn return_cached_hash_of_unchanged_file() -> anyhow::Result<()> {
+        let file = temp_file("hit", b"image")?;
+        let hash_calls = Cell::new(0);
+        let hash_file = |_ : &Path| { hash_calls.set(hash_calls.get() + 1); Ok(ImageHash(42)) };
+        let mut cache = HashCache::new();
+
+        assert_eq!(cache.get_or_hash(&file, hash_file)?, ImageHash(42));
+        assert_eq!(cache.get_or_hash(&file, hash_file)?, ImageHash(42));
+
+        assert_eq!(hash_calls.get(), 1);
+        assert_eq!(cache.len(), 1);
+        fs::remove_file(file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn hash_file_again_after_modification_time_changes() -> anyhow::Result<()> {
+        let file = temp_file("miss", b"image")?;
+        let mut cache = HashCache::new();
+        cache.get_or_hash(&file, |_| Ok(ImageHash(1)))?;
+
+        File::options().write(true).open(&file)?.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+
+        assert_eq!(cache.get_or_hash(&file, |_| Ok(ImageHash(2)))?, ImageHash(2));
+        assert_eq!(cache.get_or_hash(&file, |_| Ok(ImageHash(3)))?, ImageHash(2));
+        fs::remove_file(file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_and_deserialize_cache() -> anyhow::Result<()> {
+        let first_file = temp_file("first", b"image")?;
+        let second_file = temp_file("second", b"other image")?;
+        let cache_file = std::env::temp_dir().join(format!("imgcmp-cache-{}.json", std::process::id()));
+        let mut cache = HashCache::new();
+        cache.get_or_hash(&first_file, |_| Ok(ImageHash(1)))?;
+        cache.get_or_hash(&second_file, |_| Ok(ImageHash(u64::MAX)))?;
+
+        cache.save(&cache_file)?;
+        let mut loaded_cache = HashCache::load(&cache_file)?;
+
+        assert_eq!(loaded_cache, cache);
+        assert_eq!(loaded_cache.get_or_hash(&second_file, |_| Ok(ImageHash(0)))?, ImageHash(u64::MAX));
+        assert!(matches!(HashCache::from_json("{"), Err(ImgcmpError::InvalidCache(_))));
+        for file in [first_file, second_file, cache_file] {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+}
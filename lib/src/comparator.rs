@@ -0,0 +1,131 @@
+use crate::{Image, ImageHash, PerceptualHasher, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// A hashing algorithm that decides whether two images are the same by comparing their hashes.
+/// Implemented by the hashers of the library, and by downstream crates wishing to plug in their own algorithm.
+pub trait Comparator {
+    /// Calculates the hash of the passed image.
+    fn hash(&self, image : &Image) -> Result<ImageHash>;
+
+    /// Checks whether the passed hashes belong to images that should be considered the same.
+    fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool;
+
+    /// Checks that the passed image would be accepted by `hash`, without hashing it, so that byte-for-byte
+    /// equal images are rejected like any other. Accepts all images by default.
+    fn check_image(&self, _image : &Image) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Comparator for PerceptualHasher {
+    fn hash(&self, image : &Image) -> Result<ImageHash> {
+        PerceptualHasher::hash(self, image)
+    }
+
+    fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool {
+        PerceptualHasher::are_same(self, left_hash, right_hash)
+    }
+
+    fn check_image(&self, image : &Image) -> Result<()> {
+        self.check_source_size(image)
+    }
+}
+
+/// Comparator remembering the hashes of the most recently hashed images, so that comparing the same query image
+/// against many references, repeatedly, hashes the query only once. Images are looked up by a fingerprint of their
+/// dimensions and pixels, and a copy of each cached image is kept and compared with the looked up image, so that
+/// images of colliding fingerprints are never mistaken for each other. Computing the fingerprint and comparing
+/// the images read all pixels once, but skip scaling the image down and computing its DCT.
+/// At most `capacity` hashes are kept, the least recently used being forgotten first, and lookups are linear in `capacity`.
+#[derive (Debug)]
+pub struct CachingComparator<C> {
+    comparator : C,
+    capacity : usize,
+    /// From the most to the least recently used
+    hashes : Mutex<VecDeque<CachedHash>>
+}
+
+#[derive (Debug)]
+struct CachedHash {
+    fingerprint : u64,
+    image : Image,
+    hash : ImageHash
+}
+
+impl<C : Comparator> CachingComparator<C> {
+    /// Creates a comparator hashing images with `comparator` and remembering the hashes of the `capacity`
+    /// most recently hashed images. A capacity of zero remembers no hashes.
+    pub fn new(comparator : C, capacity : usize) -> CachingComparator<C> {
+        CachingComparator { comparator, capacity, hashes : Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    /// The wrapped comparator, which hashes the images missing from the cache
+    pub fn get_comparator(&self) -> &C {
+        &self.comparator
+    }
+
+    fn fingerprint(image : &Image) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        image.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl<C : Comparator> Comparator for CachingComparator<C> {
+    fn hash(&self, image : &Image) -> Result<ImageHash> {
+        let fingerprint = Self::fingerprint(image);
+        {
+            let mut hashes = self.hashes.lock().unwrap_or_else(|error| error.into_inner());
+            if let Some(index) = hashes.iter().position(|cached| cached.fingerprint == fingerprint && cached.image == *image) {
+                let entry = hashes.remove(index).expect("index of a cached hash");
+                let hash = entry.hash;
+                hashes.push_front(entry);
+                return Ok(hash);
+            }
+        }
+
+        // Hash without holding the lock, so that other threads can look up their images meanwhile
+        let hash = self.comparator.hash(image)?;
+        if self.capacity > 0 {
+            let mut hashes = self.hashes.lock().unwrap_or_else(|error| error.into_inner());
+            hashes.push_front(CachedHash { fingerprint, image : image.clone(), hash });
+            hashes.truncate(self.capacity);
+        }
+        Ok(hash)
+    }
+
+    fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool {
+        self.comparator.are_same(left_hash, right_hash)
+    }
+
+    fn check_image(&self, image : &Image) -> Result<()> {
+        self.comparator.check_image(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn do_not_return_hash_of_image_of_colliding_fingerprint() -> anyhow::Result<()> {
+        let image = Image::from_luma(&[10, 200, 30, 40], 2)?;
+        let other_image = Image::from_luma(&[50, 60, 70, 80], 2)?;
+        let hasher = PerceptualHasher::new(crate::Config::default());
+        let comparator = CachingComparator::new(PerceptualHasher::new(crate::Config::default()), 4);
+        let colliding_hash = ImageHash(!hasher.hash(&image)?.0);
+        comparator.hashes.lock().unwrap().push_front(CachedHash {
+            fingerprint : CachingComparator::<PerceptualHasher>::fingerprint(&image),
+            image : other_image,
+            hash : colliding_hash
+        });
+
+        assert_eq!(Comparator::hash(&comparator, &image)?, hasher.hash(&image)?);
+        assert_eq!(Comparator::hash(&comparator, &image.clone())?, hasher.hash(&image)?);
+        assert_eq!(comparator.hashes.lock().unwrap().len(), 2);
+        Ok(())
+    }
+}
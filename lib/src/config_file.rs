@@ -0,0 +1,116 @@
+use crate::{Config, ImgcmpError, Result};
+use std::path::Path;
+
+impl Config {
+    /// Parses a configuration from TOML. Every field of `Config` is a top-level key, fields missing
+    /// from the TOML keep their default values. Unit enum variants are strings, e.g. `threshold = "Median"`,
+    /// variants with data are inline tables, e.g. `grayscale_mode = { Channel = 1 }`, and tuples and arrays
+    /// are arrays. The parsed configuration is checked with `validate`.
+    pub fn from_toml_str(toml : &str) -> Result<Config> {
+        let config : Config = toml::from_str(toml).map_err(|error| ImgcmpError::InvalidConfig(error.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads a configuration from the TOML file at `path`, see `from_toml_str`. Errors mention the path of the file.
+    pub fn from_toml_path<P : AsRef<Path>>(path : P) -> Result<Config> {
+        let path = path.as_ref();
+        let with_path = |reason : String| format!("{}: {}", path.display(), reason);
+        let toml = std::fs::read_to_string(path).map_err(|error| ImgcmpError::Io(with_path(error.to_string())))?;
+        Config::from_toml_str(&toml).map_err(|error| match error {
+            ImgcmpError::InvalidConfig(reason) => ImgcmpError::InvalidConfig(with_path(reason)),
+            error => error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DistanceMetric, GrayscaleMode, ThresholdStrategy};
+
+    #[test]
+    fn load_config_file() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("imgcmp-config-{}.toml", std::process::id()));
+        std::fs::write(&path, r#"
+            # Matching parameters of the photo library
+            dct_dimension = 64
+            allowed_distance = 5
+            threshold = "Median"  # robust to outliers
+            grayscale_mode = { Channel = 1 }
+            distance_metric = { NormalizedHamming = { max_distance = 0.1 } }
+            composite_background = [255, 255, 255]
+            blur_sigma = 1.5
+            equalize_histogram = true
+        "#)?;
+
+        let config = Config::from_toml_path(&path);
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(config?, Config {
+            dct_dimension : 64,
+            allowed_distance : 5,
+            threshold : ThresholdStrategy::Median,
+            grayscale_mode : GrayscaleMode::Channel(1),
+            distance_metric : DistanceMetric::NormalizedHamming { max_distance : 0.1 },
+            composite_background : Some([255, 255, 255]),
+            blur_sigma : Some(1.5),
+            equalize_histogram : true,
+            ..Config::default()
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn keep_defaults_of_empty_file() -> anyhow::Result<()> {
+        assert_eq!(Config::from_toml_str("")?, Config::default());
+        assert_eq!(Config::from_toml_str("# no overrides\n\n")?, Config::default());
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_for_malformed_file() {
+        let is_invalid = |toml : &str| matches!(Config::from_toml_str(toml), Err(ImgcmpError::InvalidConfig(_)));
+
+        assert!(is_invalid("allowed_distance = "));
+        assert!(is_invalid("allowed_distance 3"));
+        assert!(is_invalid("threshold = \"Median"));
+        assert!(is_invalid("allowed_distance = 3 4"));
+        assert!(is_invalid("allowed_distance = 3\nallowed_distance = 4"));
+        assert!(is_invalid("[hasher]\nallowed_distance = 3"));
+        assert!(is_invalid("allowed_distance = \"three\""));
+        assert!(is_invalid("unknown_field = 1"));
+        assert!(matches!(Config::from_toml_str("dct_dimension = 32\nthreshold = Median"),
+                         Err(ImgcmpError::InvalidConfig(reason)) if reason.contains("line 2")));
+    }
+
+    #[test]
+    fn accept_any_toml_syntax() -> anyhow::Result<()> {
+        let config = Config::from_toml_str(r#"
+            threshold = 'Median'
+            distance_metric.NormalizedHamming.max_distance = 0.1
+            dct_dimensions = [
+                16,
+                32,
+            ]
+
+            [grayscale_mode]
+            Channel = 1
+        "#)?;
+
+        assert_eq!(config, Config {
+            threshold : ThresholdStrategy::Median,
+            distance_metric : DistanceMetric::NormalizedHamming { max_distance : 0.1 },
+            dct_dimensions : vec!(16, 32),
+            grayscale_mode : GrayscaleMode::Channel(1),
+            ..Config::default()
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_for_invalid_config() {
+        assert!(matches!(Config::from_toml_str("allowed_distance = 100"), Err(ImgcmpError::InvalidConfig(_))));
+        assert!(matches!(Config::from_toml_path("missing.toml"), Err(ImgcmpError::Io(_))));
+    }
+}
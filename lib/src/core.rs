@@ -0,0 +1,449 @@
+//! Pure computations used for hashing. This module only depends on `core` and `alloc`
+//! so that it can be used on `no_std` targets.
+
+use alloc::vec::Vec;
+use ::core::f32::consts::{PI, SQRT_2};
+
+/// Computes the Hamming distance between the passed bitmaps
+pub fn hamming_distance(hash1 : u64, hash2 : u64) -> u8 {
+    let xor = hash1 ^ hash2;
+    xor.count_ones() as u8
+}
+
+/// Computes the fraction of differing bits of the passed bitmaps, from 0.0 for identical bitmaps
+/// to 1.0 for inverted ones, e.g. for ranking matches or as a feature of other models.
+pub fn normalized_distance(hash1 : u64, hash2 : u64) -> f32 {
+    normalized_distance_of_width(hash1, hash2, 64)
+}
+
+/// Computes the fraction of differing bits of bitmaps of `bits` bits, stored in their least significant bits,
+/// e.g. of hashes of `Config::hash_bit_count` bits. Higher bits are ignored, and widths above 64 are clamped.
+/// Empty bitmaps are at distance 0.0.
+pub fn normalized_distance_of_width(hash1 : u64, hash2 : u64, bits : u32) -> f32 {
+    let bits = bits.min(64);
+    if bits == 0 {
+        return 0.0;
+    }
+    let mask = u64::MAX >> (64 - bits);
+    hamming_distance(hash1 & mask, hash2 & mask) as f32 / bits as f32
+}
+
+/// Computes the Hamming distances between the query hash and each of the candidate hashes.
+/// Candidates are processed in fixed size chunks, which the compiler can vectorize on targets
+/// with a vector popcount (e.g. when building with `-C target-cpu=native`). On other targets
+/// it performs the same as calling `hamming_distance` in a loop.
+pub fn hamming_distances(query : u64, candidates : &[u64]) -> Vec<u8> {
+    const LANES : usize = 8;
+
+    let mut distances = Vec::with_capacity(candidates.len());
+    let chunks = candidates.chunks_exact(LANES);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        let mut chunk_distances = [0_u8; LANES];
+        for (distance, candidate) in chunk_distances.iter_mut().zip(chunk) {
+            *distance = (query ^ candidate).count_ones() as u8;
+        }
+        distances.extend_from_slice(&chunk_distances);
+    }
+    distances.extend(remainder.iter().map(|candidate| hamming_distance(query, *candidate)));
+    distances
+}
+
+/// Counts the candidate hashes within `max_distance` of the query hash
+pub fn count_matches(query : u64, candidates : &[u64], max_distance : u8) -> usize {
+    candidates.iter().filter(|candidate| hamming_distance(query, **candidate) <= max_distance).count()
+}
+
+/// Returns the indices of the candidate hashes within `max_distance` of the query hash
+pub fn find_matches(query : u64, candidates : &[u64], max_distance : u8) -> Vec<usize> {
+    candidates.iter().enumerate().
+        filter(|(_, candidate)| hamming_distance(query, **candidate) <= max_distance).
+        map(|(index, _)| index).
+        collect()
+}
+
+/// Computes a distance between the passed bitmaps that sums the weight of each differing bit,
+/// the first weight being the one of the least significant bit. Bits without a weight are ignored.
+pub fn compare_hashes_weighted(hash1 : u64, hash2 : u64, weights : &[f32]) -> f32 {
+    let xor = hash1 ^ hash2;
+    weights.iter().take(64).enumerate().
+        filter(|(bit, _)| xor & (1 << bit) != 0).
+        map(|(_, weight)| weight).
+        sum()
+}
+
+/// Weights of the coefficients at the passed (horizontal, vertical) frequencies for
+/// `compare_hashes_weighted`, decaying from 1 for the lowest frequencies, which carry most of
+/// the perceptual weight, as the sum of their frequencies grows.
+pub fn frequency_weights(positions : &[(usize, usize)]) -> Vec<f32> {
+    positions.iter().
+        map(|(horizontal, vertical)| 1.0 / (horizontal + vertical).max(1) as f32).
+        collect()
+}
+
+/// Packs a slice of 1s and 0s into a 64 bit bitmap, the first element being the least significant bit.
+/// Returns `None` when more than 64 bits are passed.
+pub fn pack_bits(bits : &[u8]) -> Option<u64> {
+    if bits.len() > 64 {
+        return None;
+    }
+
+    let hash = bits.iter().enumerate().
+        fold(0_u64, |hash, (index, bit)| hash | ((*bit as u64) << index));
+    Some(hash)
+}
+
+/// Computes the 1-D DCT of the passed signal, using the same scaling as the 2-D DCT used for hashing,
+/// i.e. applying the transform on all rows and then on all columns of an image gives its 2-D DCT.
+pub fn dct_1d(signal : &[f32]) -> Vec<f32> {
+    let len = signal.len() as f32;
+    let c = |x| if x == 0 {1.0 / SQRT_2} else {1.0};
+
+    (0..signal.len()).map(|k| {
+        let sum = signal.iter().enumerate().fold(0.0, |sum, (n, value)| {
+            sum + value * libm::cosf(PI * k as f32 * (n as f32 + 0.5) / len)
+        });
+        0.5 * c(k) * sum
+    }).collect()
+}
+
+/// Computes the 2-D DCT of a `dim`x`dim` row-major buffer, by applying `dct_1d` on all rows and then
+/// on all columns. The returned buffer is row-major as well, with vertical frequencies along rows
+/// and horizontal frequencies along columns.
+pub fn dct_2d(pixels : &[f32], dim : usize) -> Vec<f32> {
+    // Transforming the mean-centered pixels leaves the AC terms unchanged, but makes them exactly
+    // zero for uniform blocks instead of rounding noise
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+    let centered : Vec<f32> = pixels.iter().map(|p| p - mean).collect();
+    let mut coefficients : Vec<f32> = centered.chunks(dim).flat_map(dct_1d).collect();
+
+    let mut column = Vec::with_capacity(dim);
+    for x in 0..dim {
+        column.clear();
+        column.extend((0..dim).map(|y| coefficients[y * dim + x]));
+        for (y, coefficient) in dct_1d(&column).into_iter().enumerate() {
+            coefficients[y * dim + x] = coefficient;
+        }
+    }
+    // 0.5 * c(0) applied once per dimension
+    coefficients[0] = 0.125 * pixels.iter().sum::<f32>();
+
+    coefficients
+}
+
+/// Takes the top left `reduced_dim`x`reduced_dim` corner of the passed row-major `dim`x`dim`
+/// coefficients and converts them to bits, based on whether they are below or above their average,
+/// excluding the first term. Coefficients within `tolerance` of the average are always converted to 0,
+/// coefficients equal to the average are converted according to `tie_breaking`.
+/// Returns the bits in row-major order, along with the average used as threshold.
+pub fn reduce_coefficients(coefficients : &[f32], dim : usize, reduced_dim : usize, tolerance : f32,
+                           tie_breaking : TieBreaking) -> (Vec<u8>, f32) {
+    let coefficient_at = |x : usize, y : usize| {
+        if (x, y) == (0, 0) || x >= dim || y >= dim { 0.0 } else { coefficients[y * dim + x] }
+    };
+
+    let reduced_coefficients : Vec<f32> = (0..reduced_dim).
+        flat_map(|y| (0..reduced_dim).map(move |x| (x, y))).
+        map(|(x, y)| coefficient_at(x, y)).
+        collect();
+    let average = reduced_coefficients.iter().sum::<f32>() / reduced_coefficients.len() as f32;
+    let bits = reduced_coefficients.iter().
+        map(|c| coefficient_to_bit(*c, average, tolerance, tie_breaking)).
+        collect();
+    (bits, average)
+}
+
+/// Order in which the coefficients of the reduced block are converted to bits, starting from
+/// the least significant bit of the hash
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum CoefficientOrder {
+    /// Row by row, with the horizontal frequency changing fastest
+    Block,
+    /// JPEG zig-zag order, so that the lowest, perceptually most significant, frequencies occupy the lowest bits
+    ZigZag
+}
+
+impl CoefficientOrder {
+    /// Positions of the coefficients of a `horizontal`x`vertical` block in this order
+    pub fn positions(self, horizontal : usize, vertical : usize) -> Vec<(usize, usize)> {
+        match self {
+            CoefficientOrder::Block => block_positions(horizontal, vertical),
+            CoefficientOrder::ZigZag => zigzag_positions(horizontal, vertical)
+        }
+    }
+}
+
+/// Positions of the coefficients of a `horizontal`x`vertical` block, as (horizontal, vertical)
+/// frequencies, with the horizontal frequency changing fastest.
+pub fn block_positions(horizontal : usize, vertical : usize) -> Vec<(usize, usize)> {
+    (0..vertical).flat_map(|y| (0..horizontal).map(move |x| (x, y))).collect()
+}
+
+/// Positions of the coefficients of a `horizontal`x`vertical` block, as (horizontal, vertical)
+/// frequencies, in JPEG zig-zag order, i.e. traversing the anti-diagonals from the lowest frequencies
+/// to the highest ones, in alternating directions.
+pub fn zigzag_positions(horizontal : usize, vertical : usize) -> Vec<(usize, usize)> {
+    let diagonals = (horizontal + vertical).saturating_sub(1);
+    (0..diagonals).flat_map(|diagonal| {
+        let diagonal_positions = (0..=diagonal).map(move |x| (x, diagonal - x));
+        let ordered : Vec<(usize, usize)> = if diagonal % 2 == 0 {
+            diagonal_positions.collect()
+        } else {
+            diagonal_positions.rev().collect()
+        };
+        ordered.into_iter().filter(|(x, y)| *x < horizontal && *y < vertical)
+    }).collect()
+}
+
+/// How the threshold separating the 0 bits from the 1 bits is derived from the reduced coefficients
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum ThresholdStrategy {
+    /// Average of the coefficients
+    Mean,
+    /// Median of the coefficients, so that about half of the bits are set regardless of outliers.
+    /// The average of the two middle coefficients for an even number of coefficients.
+    Median
+}
+
+impl ThresholdStrategy {
+    /// Threshold of the passed coefficients. NaN when there are no coefficients or one of them is NaN.
+    pub fn threshold(self, coefficients : &[f32]) -> f32 {
+        match self {
+            ThresholdStrategy::Mean => coefficients.iter().sum::<f32>() / coefficients.len() as f32,
+            ThresholdStrategy::Median => {
+                if coefficients.is_empty() || coefficients.iter().any(|c| c.is_nan()) {
+                    return f32::NAN;
+                }
+                let mut sorted = coefficients.to_vec();
+                sorted.sort_by(f32::total_cmp);
+                let middle = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) { (sorted[middle - 1] + sorted[middle]) / 2.0 } else { sorted[middle] }
+            }
+        }
+    }
+}
+
+/// Decides the bit of coefficients that are exactly equal to the threshold
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum TieBreaking {
+    /// Coefficients equal to the threshold are converted to 1, i.e. `coefficient >= threshold`
+    AboveOrEqual,
+    /// Coefficients equal to the threshold are converted to 0, i.e. `coefficient > threshold`.
+    /// A uniform image, whose coefficients are all equal to the threshold, is hashed to all zeros.
+    StrictlyAbove
+}
+
+/// Converts a coefficient to a single bit based on whether it is above the passed average.
+/// Coefficients within `tolerance` of the average are always converted to 0, coefficients
+/// equal to the average are converted according to `tie_breaking`.
+pub fn coefficient_to_bit(coefficient : f32, average : f32, tolerance : f32, tie_breaking : TieBreaking) -> u8 {
+    let difference = coefficient - average;
+    let is_above = match tie_breaking {
+        TieBreaking::AboveOrEqual => difference >= 0.0,
+        TieBreaking::StrictlyAbove => difference > 0.0
+    };
+    if !is_above || (tolerance > 0.0 && difference <= tolerance) { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_frequency_differences_weigh_more_than_high_frequency_ones() {
+        let positions = block_positions(8, 8);
+        let weights = frequency_weights(&positions);
+        let low_frequency_bits = 0b1_0000_0010;
+        let high_frequency_bits = 0b1100_0000 << 56;
+
+        let low_frequency_distance = compare_hashes_weighted(0, low_frequency_bits, &weights);
+        let high_frequency_distance = compare_hashes_weighted(0, high_frequency_bits, &weights);
+
+        assert_eq!(weights[1], 1.0);
+        assert_eq!(low_frequency_distance, 2.0);
+        assert!(high_frequency_distance < low_frequency_distance);
+        assert_eq!(compare_hashes_weighted(0, u64::MAX, &[0.5; 4]), 2.0);
+    }
+
+    #[test]
+    fn normalize_distance_by_number_of_bits() {
+        let hash = 0x0123_4567_89ab_cdef;
+
+        assert_eq!(normalized_distance(hash, hash), 0.0);
+        assert_eq!(normalized_distance(hash, !hash), 1.0);
+        assert_eq!(normalized_distance(0, 0xffff_ffff), 0.5);
+        assert_eq!(normalized_distance_of_width(hash, !hash, 49), 1.0);
+        assert_eq!(normalized_distance_of_width(0b0110, 0b1010, 4), 0.5);
+        assert_eq!(normalized_distance_of_width(0b0110, u64::MAX << 4 | 0b0110, 4), 0.0);
+        assert_eq!(normalized_distance_of_width(0, u64::MAX, 128), 1.0);
+        assert_eq!(normalized_distance_of_width(0, u64::MAX, 0), 0.0);
+    }
+
+    #[test]
+    fn return_mean_or_median_threshold() {
+        let coefficients = [1.0, 100.0, 3.0, 2.0];
+
+        assert_eq!(ThresholdStrategy::Mean.threshold(&coefficients), 26.5);
+        assert_eq!(ThresholdStrategy::Median.threshold(&coefficients), 2.5);
+        assert_eq!(ThresholdStrategy::Median.threshold(&coefficients[..3]), 3.0);
+        assert!(ThresholdStrategy::Median.threshold(&[]).is_nan());
+        assert!(ThresholdStrategy::Median.threshold(&[1.0, f32::NAN, 2.0]).is_nan());
+    }
+
+    #[test]
+    fn return_hamming_distance_of_hashes() {
+        assert_eq!(hamming_distance(0b1011100100, 0b1011100100), 0);
+        assert_eq!(hamming_distance(0b1101101100, 0b1011100100), 3);
+    }
+
+    #[test]
+    fn batch_hamming_distances_are_same_with_scalar_ones() {
+        let mut candidates = [0_u64; 37];
+        let mut state = 0x9E3779B97F4A7C15_u64;
+        for candidate in candidates.iter_mut() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            *candidate = state;
+        }
+        let query = 0x0123456789ABCDEF;
+
+        let distances = hamming_distances(query, &candidates);
+
+        assert_eq!(distances.len(), candidates.len());
+        for (distance, candidate) in distances.iter().zip(candidates.iter()) {
+            assert_eq!(*distance, hamming_distance(query, *candidate));
+        }
+        assert!(hamming_distances(query, &[]).is_empty());
+    }
+
+    #[test]
+    fn count_candidates_within_distance() {
+        let candidates = [0b1011100100, 0b1011100101, 0b0100011011, 0b1011100111, 0];
+
+        assert_eq!(count_matches(0b1011100100, &candidates, 0), 1);
+        assert_eq!(count_matches(0b1011100100, &candidates, 2), 3);
+        assert_eq!(count_matches(0b1011100100, &candidates, 64), 5);
+        assert_eq!(count_matches(0b1011100100, &[], 64), 0);
+    }
+
+    #[test]
+    fn find_candidates_within_distance() {
+        let candidates = [0b1011100100, 0b1011100101, 0b0100011011, 0b1011100111, 0];
+
+        assert_eq!(find_matches(0b1011100100, &candidates, 0), [0]);
+        assert_eq!(find_matches(0b1011100100, &candidates, 2), [0, 1, 3]);
+        assert_eq!(find_matches(0b0100011011, &candidates, 5), [2, 4]);
+    }
+
+    #[test]
+    fn pack_bits_of_array() {
+        let bits = [0, 1, 1, 1, 1, 0, 0, 1, 0];
+
+        assert_eq!(pack_bits(&bits), Some(0b010011110));
+    }
+
+    #[test]
+    fn do_not_pack_more_than_64_bits() {
+        let bits = [0; 65];
+
+        assert_eq!(pack_bits(&bits), None);
+    }
+
+    #[test]
+    fn calculate_dct_of_constant_array() {
+        let signal = [10.0; 8];
+
+        let coefficients = dct_1d(&signal);
+
+        let expected_dc = 0.5 / SQRT_2 * 80.0;
+        assert!((coefficients[0] - expected_dc).abs() < 0.001);
+        assert!(coefficients[1..].iter().all(|c| c.abs() < 0.001));
+    }
+
+    #[test]
+    fn calculate_dct_of_array() {
+        let signal = [144.0, 139.0, 149.0, 155.0, 153.0, 155.0, 155.0, 155.0];
+
+        let coefficients = dct_1d(&signal);
+
+        let expected_coefficients = [426.03, -13.52, -6.07, -0.63, 3.18, 5.04, 2.9, -0.1];
+        for (coefficient, expected) in coefficients.iter().zip(expected_coefficients.iter()) {
+            assert!((coefficient - expected).abs() < 0.1, "{} != {}", coefficient, expected);
+        }
+    }
+
+    #[test]
+    fn calculate_dct_of_constant_block() {
+        let pixels = [10.0; 16];
+
+        let coefficients = dct_2d(&pixels, 4);
+
+        // 0.25 * c(0) * c(0) * 16 * 10
+        assert!((coefficients[0] - 20.0).abs() < 0.001);
+        assert!(coefficients[1..].iter().all(|c| c.abs() < 0.001));
+    }
+
+    #[test]
+    fn reduce_top_left_corner_of_coefficients() {
+        let coefficients = [
+            1000.0, 4.0, -2.0,
+            6.0, 1.0, 30.0,
+            -8.0, 5.0, 50.0];
+
+        let (bits, threshold) = reduce_coefficients(&coefficients, 3, 2, 0.0, TieBreaking::StrictlyAbove);
+
+        assert_eq!(threshold, 2.75);
+        assert_eq!(bits, [0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn return_block_positions_with_horizontal_frequency_changing_fastest() {
+        assert_eq!(block_positions(3, 2), [(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn return_zigzag_positions_of_rectangular_block() {
+        assert_eq!(zigzag_positions(3, 2), [(0, 0), (1, 0), (0, 1), (1, 1), (2, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn return_jpeg_zigzag_sequence_of_8x8_block() {
+        let expected_indices = [
+            0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+            12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+            35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+            58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63];
+
+        let indices : Vec<usize> = CoefficientOrder::ZigZag.positions(8, 8).iter().map(|(x, y)| y * 8 + x).collect();
+
+        assert_eq!(indices, expected_indices);
+    }
+
+    #[test]
+    fn break_ties_with_threshold_as_configured() {
+        let coefficients = [
+            1000.0, 3.0, 7.0,
+            1.0, 0.0, 5.0,
+            9.0, 8.0, 7.0];
+
+        let (above_or_equal, threshold) = reduce_coefficients(&coefficients, 3, 2, 0.0, TieBreaking::AboveOrEqual);
+        let (strictly_above, _) = reduce_coefficients(&coefficients, 3, 2, 0.0, TieBreaking::StrictlyAbove);
+
+        assert_eq!(threshold, 1.0);
+        assert_eq!(above_or_equal, [0, 1, 1, 0]);
+        assert_eq!(strictly_above, [0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn calculate_exactly_zero_ac_terms_of_uniform_block() {
+        let pixels = [77.0; 64];
+
+        let coefficients = dct_2d(&pixels, 8);
+
+        assert!(coefficients[1..].iter().all(|c| *c == 0.0));
+        let (bits, _) = reduce_coefficients(&coefficients, 8, 8, 0.0, TieBreaking::StrictlyAbove);
+        assert_eq!(pack_bits(&bits), Some(0));
+    }
+}
@@ -1,8 +1,10 @@
 use crate::image::*;
+use crate::core::{self, ThresholdStrategy, TieBreaking};
 use std::f32::consts::PI;
 use nalgebra::DMatrix;
 use ndarray::Array2;
-use anyhow::{anyhow};
+use crate::error::{ImgcmpError, Result};
+use std::sync::{Arc, Mutex};
 
 /// Calculates DCT basis matrix for all horizontal and vertical frequencies
 pub fn calc_dct_basis(dim : u32) -> Array2<DMatrix<f32>> {
@@ -12,72 +14,217 @@ pub fn calc_dct_basis(dim : u32) -> Array2<DMatrix<f32>> {
     Array2::from_shape_fn((dim as usize, dim as usize), matrix_at)
 }
 
+/// Largest dimension whose basis is cached by `cached_dct_basis`, so that the cache holds at most
+/// `MAX_CACHED_BASES` bases of 32^4 coefficients, i.e. 16 MB
+const MAX_CACHED_DIMENSION : u32 = 32;
+const MAX_CACHED_BASES : usize = 4;
+
+/// Dimension of a cached basis, along with the basis
+type CachedBasis = (u32, Arc<Array2<DMatrix<f32>>>);
+
+/// Bases of the most recently used dimensions, from the most to the least recently used
+static CACHED_BASES : Mutex<Vec<CachedBasis>> = Mutex::new(Vec::new());
+
+#[cfg(test)]
+thread_local! {
+    /// Cache hits of the current thread, so that tests running in parallel do not count the hits of each other
+    static CACHE_HITS : std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Returns the DCT basis of the passed dimension like `calc_dct_basis`, reusing the bases of recently used dimensions,
+/// so that creating many hashers of the same configuration, e.g. on every call of `compare_images`, is cheap.
+/// Bases of dimensions above 32 are not cached, since they take too much memory.
+pub fn cached_dct_basis(dim : u32) -> Arc<Array2<DMatrix<f32>>> {
+    if dim > MAX_CACHED_DIMENSION {
+        return Arc::new(calc_dct_basis(dim));
+    }
+
+    let lock = || CACHED_BASES.lock().unwrap_or_else(|error| error.into_inner());
+    {
+        let mut bases = lock();
+        if let Some(index) = bases.iter().position(|(cached_dim, _)| *cached_dim == dim) {
+            let entry = bases.remove(index);
+            bases.insert(0, entry.clone());
+            #[cfg(test)]
+            CACHE_HITS.with(|hits| hits.set(hits.get() + 1));
+            return entry.1;
+        }
+    }
+
+    // Calculate without holding the lock, so that hashers of other dimensions are not blocked meanwhile
+    let basis = Arc::new(calc_dct_basis(dim));
+    let mut bases = lock();
+    bases.retain(|(cached_dim, _)| *cached_dim != dim);
+    bases.insert(0, (dim, basis.clone()));
+    bases.truncate(MAX_CACHED_BASES);
+    basis
+}
+
 fn calc_dct_basis_at(dim : u32, k : usize, l : usize, m : usize, n : usize) -> f32 {
     let two_pi = 2.0 * PI;
     let two_dim = 2.0 * dim as f32;
-    let horiz_cos = f32::cos(two_pi * (l as f32 / two_dim) * (n as f32 + 0.5));
-    let vert_cos = f32::cos(two_pi * (k as f32 / two_dim) * (m as f32 + 0.5));
-    return horiz_cos * vert_cos;
+    // The cosine of libm, unlike the one of the platform, gives the same results everywhere
+    let horiz_cos = libm::cosf(two_pi * (l as f32 / two_dim) * (n as f32 + 0.5));
+    let vert_cos = libm::cosf(two_pi * (k as f32 / two_dim) * (m as f32 + 0.5));
+    horiz_cos * vert_cos
+}
+
+/// Calculates the DCT coefficients for the passed single channel image.
+/// The coefficients are reproducible, i.e. bit for bit the same on every platform, since the basis uses a portable
+/// cosine and the terms of each coefficient are summed in a fixed order with compensated summation.
+pub fn calc_dct_coefficients<S : ImageSource + ?Sized>(image : &S, dct_basis : &Array2<DMatrix<f32>>) -> DMatrix<f32> {
+    let mean = mean_pixel(image);
+    DMatrix::<f32>::from_fn(image.get_width() as usize, image.get_height() as usize,
+                            |k, l| calc_dct_coefficient(image, dct_basis, mean, k, l))
+}
+
+/// Images of fewer pixels are not worth the overhead of computing their coefficients in parallel
+#[cfg(feature = "rayon")]
+const MIN_PARALLEL_PIXELS : usize = 16 * 16;
+
+/// Calculates the DCT coefficients for the passed image like `calc_dct_coefficients`,
+/// computing the coefficients in parallel. Images of fewer than 16x16 pixels are computed serially.
+#[cfg(feature = "rayon")]
+pub fn calc_dct_coefficients_parallel<S : ImageSource + Sync + ?Sized>(image : &S, dct_basis : &Array2<DMatrix<f32>>) -> DMatrix<f32> {
+    use rayon::prelude::*;
+
+    let (width, height) = (image.get_width() as usize, image.get_height() as usize);
+    if width * height < MIN_PARALLEL_PIXELS {
+        return calc_dct_coefficients(image, dct_basis);
+    }
+    let mean = mean_pixel(image);
+    // Column-major, like the matrices of nalgebra
+    let coefficients = (0..width * height).into_par_iter().
+        map(|index| calc_dct_coefficient(image, dct_basis, mean, index % width, index / width)).
+        collect();
+    DMatrix::<f32>::from_vec(width, height, coefficients)
 }
 
-/// Calculates the DCT coefficients for the passed image.
-pub fn calc_dct_coefficients(image : &Image, dct_basis : &Array2<DMatrix<f32>>) -> DMatrix<f32> {
+fn mean_pixel<S : ImageSource + ?Sized>(image : &S) -> f32 {
+    let pixel_count = image.get_width() as u64 * image.get_height() as u64;
+    // Summing integers is exact, regardless of the order of the pixels
+    let sum = (0..image.get_width()).
+        flat_map(|m| (0..image.get_height()).map(move |n| (m, n))).
+        map(|(m, n)| image.get_pixel(m, n)[0] as u64).
+        sum::<u64>();
+    (sum as f64 / pixel_count as f64) as f32
+}
+
+fn calc_dct_coefficient<S : ImageSource + ?Sized>(image : &S, dct_basis : &Array2<DMatrix<f32>>, mean : f32, k : usize, l : usize) -> f32 {
     let c = |x| if x == 0 {1.0 / std::f32::consts::SQRT_2} else {1.0};
 
-    let coefficients = DMatrix::<f32>::from_fn(image.get_width() as usize, image.get_height() as usize,
-        |k, l| {
-            let a = 0.25 * c(k) * c(l);
-            let mut sum = 0.0;
-            let dct_mat = dct_basis.get((k, l)).unwrap();
-            for m in 0..image.get_width() {
-                for n in 0..image.get_height() {
-                    let color = image.get_pixel(m, n)[0] as f32;
-                    sum += color * dct_mat[(m as usize, n as usize)];
-                }
-            }
-            a * sum
-        });
-
-    coefficients
+    let a = 0.25 * c(k) * c(l);
+    let dct_mat = dct_basis.get((k, l)).unwrap();
+    // Mean-centered pixels give the same AC terms, but exactly zero ones for uniform images
+    let offset = if (k, l) == (0, 0) { 0.0 } else { mean };
+    let terms = (0..image.get_width()).
+        flat_map(|m| (0..image.get_height()).map(move |n| (m, n))).
+        map(|(m, n)| (image.get_pixel(m, n)[0] as f32 - offset) * dct_mat[(m as usize, n as usize)]);
+    a * compensated_sum(terms)
 }
 
-/// Takes the top left "corner" of the passed DCT coefficients, computes the average and
-/// converts them to single bit, based on whether they are below or above the average.
-pub fn reduce_dct_coefficients(coefficients : DMatrix<f32>, dct_reduced_dimension : u32) -> DMatrix<u8> {
-    let mut reduced_coefficients = coefficients.resize(dct_reduced_dimension as usize,
-                                                   dct_reduced_dimension as usize,
-                                                   0.0);
-    // Exclude first term which is significantly different than other terms
-    reduced_coefficients[(0, 0)] = 0.0;
-    let average_coefficient = reduced_coefficients.mean();
-    reduced_coefficients.map(|c| if c < average_coefficient { 0 } else { 1 })
+/// Kahan summation, which keeps the rounding error of the sum independent of the number of terms
+fn compensated_sum<I : Iterator<Item = f32>>(terms : I) -> f32 {
+    let mut sum = 0.0f32;
+    let mut compensation = 0.0f32;
+    for term in terms {
+        let corrected_term = term - compensation;
+        let new_sum = sum + corrected_term;
+        compensation = (new_sum - sum) - corrected_term;
+        sum = new_sum;
+    }
+    sum
 }
 
-/// Convert passed Matrix to a 64 bitmap. Passed matrix should only contain 1s or 0s.
-/// Matrices with more than 64 elements are not allowed.
-pub fn hash_coefficients(coefficients : &DMatrix<u8>) -> anyhow::Result<u64> {
-    if coefficients.len() > 64 {
-        return Err(anyhow!("Matrices of more than 64 elements are not allowed"));
+/// Takes the DCT coefficients at the passed (horizontal, vertical) positions, computes their threshold
+/// according to `strategy` and converts them to single bit, based on whether they are below or above the threshold.
+/// Positions outside of the passed coefficients are treated as zero coefficients, and so is the DC term,
+/// which the median threshold excludes, always converting it to 0.
+/// Coefficients within `tolerance` of the threshold are always converted to 0, coefficients
+/// equal to the threshold are converted according to `tie_breaking`.
+/// Returns the bits in the order of the positions, along with the threshold.
+/// Fails when the threshold is not finite, e.g. when no positions are passed or a coefficient is NaN.
+pub fn reduce_dct_coefficients(coefficients : &DMatrix<f32>, positions : &[(usize, usize)], strategy : ThresholdStrategy,
+                               tolerance : f32, tie_breaking : TieBreaking) -> Result<(Vec<u8>, f32)> {
+    let coefficient_at = |(horizontal, vertical) : (usize, usize)| {
+        // Exclude first term which is significantly different than other terms
+        if (horizontal, vertical) == (0, 0) || horizontal >= coefficients.nrows() || vertical >= coefficients.ncols() {
+            0.0
+        } else {
+            coefficients[(horizontal, vertical)]
+        }
+    };
+
+    let reduced_coefficients : Vec<f32> = positions.iter().map(|position| coefficient_at(*position)).collect();
+    let threshold = match strategy {
+        // The zero standing for the DC term would sit among the middle AC coefficients, which are centered around
+        // zero, so the median would, along with the bit of the DC term, flip with the sign of its neighbour
+        ThresholdStrategy::Median => {
+            let ac_coefficients : Vec<f32> = positions.iter().
+                filter(|position| **position != (0, 0)).
+                map(|position| coefficient_at(*position)).
+                collect();
+            strategy.threshold(&ac_coefficients)
+        },
+        ThresholdStrategy::Mean => strategy.threshold(&reduced_coefficients)
+    };
+    if !threshold.is_finite() {
+        return Err(ImgcmpError::InvalidCoefficients(
+            format!("{:?} threshold of {} coefficients is {}", strategy, reduced_coefficients.len(), threshold)));
     }
 
-    let (_, hash) = coefficients.fold((0 as u64, 0 as u64),
-        |(index, hash), c| (index + 1, hash | ((c as u64) << index)));
-    Ok(hash)
+    let bits = reduced_coefficients.iter().zip(positions.iter()).
+        map(|(c, position)| match strategy {
+            ThresholdStrategy::Median if *position == (0, 0) => 0,
+            _ => core::coefficient_to_bit(*c, threshold, tolerance, tie_breaking)
+        }).
+        collect();
+    Ok((bits, threshold))
+}
+
+/// Convert passed bits to a 64 bitmap, the first bit being the least significant one.
+/// Passed slice should only contain 1s or 0s. More than 64 bits are not allowed.
+/// Not tied to the DCT, so it can pack the bits of any hashing algorithm.
+pub fn hash_bits(bits : &[u8]) -> Result<u64> {
+    core::pack_bits(bits).
+        ok_or(ImgcmpError::HashTooLarge { bits : bits.len(), max_bits : 64 })
+}
+
+/// Convert the bits of reduced DCT coefficients to a 64 bitmap. See `hash_bits`.
+pub fn hash_coefficients(bits : &[u8]) -> Result<u64> {
+    hash_bits(bits)
 }
 
 /// Computes the Hamming distance between the passed bitmaps
 pub fn compare_hashes(hash1 : u64, hash2 : u64) -> u8 {
-    let xor = hash1 ^ hash2;
-    xor.count_ones() as u8
+    core::hamming_distance(hash1, hash2)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{compare_images, Config};
+
+    #[test]
+    fn reuse_cached_basis_across_comparisons() -> anyhow::Result<()> {
+        // No other test uses this dimension, and tests of other dimensions would have to fill the whole cache
+        // between both comparisons for evicting it
+        let image = Image::from_fn(54, 54, 1, |x, y| vec!((x * 5 + y * 3) as u8));
+        let config = Config { dct_dimension : 27, ..Config::default() };
+
+        compare_images(&image, &image.invert(), config.clone())?;
+        let hits = CACHE_HITS.with(|hits| hits.get());
+        compare_images(&image, &image.invert(), config)?;
+
+        assert_eq!(CACHE_HITS.with(|hits| hits.get()), hits + 1);
+        assert!(!Arc::ptr_eq(&cached_dct_basis(40), &cached_dct_basis(40)));
+        assert_eq!(*cached_dct_basis(27), calc_dct_basis(27));
+        Ok(())
+    }
 
     #[test]
     fn calculate_dct_example() -> anyhow::Result<()> {
-        let image = Image::from(&[
+        let image = Image::from_luma(&[
             144, 139, 149, 155, 153, 155, 155, 155,
             151, 151, 151, 159, 156, 156, 156, 158,
             151, 156, 160, 162, 159, 151, 151, 151,
@@ -85,7 +232,7 @@ mod tests {
             158, 160, 161, 162, 160, 155, 155, 156,
             161, 161, 161, 161, 160, 157, 157, 157,
             162, 162, 161, 160, 161, 157, 157, 157,
-            162, 162, 161, 160, 163, 157, 158, 154], 8, 1)?;
+            162, 162, 161, 160, 163, 157, 158, 154], 8)?;
 
         let coefficients = calc_dct_coefficients(&image, &calc_dct_basis(8));
 
@@ -105,6 +252,187 @@ mod tests {
         Ok(())
     }
 
+    /// Straightforward DCT in double precision and with the scaling of `calc_dct_coefficient`,
+    /// as a reference for the single precision DCT
+    fn reference_dct(pixels : &[u8], dim : usize) -> Vec<f64> {
+        let c = |x| if x == 0 { 1.0 / std::f64::consts::SQRT_2 } else { 1.0 };
+        let basis = |frequency : usize, position : usize| {
+            (std::f64::consts::PI * frequency as f64 * (position as f64 + 0.5) / dim as f64).cos()
+        };
+        (0..dim * dim).map(|index| {
+            let (k, l) = (index % dim, index / dim);
+            let sum : f64 = (0..dim * dim).
+                map(|pixel| pixels[pixel] as f64 * basis(k, pixel % dim) * basis(l, pixel / dim)).
+                sum();
+            0.25 * c(k) * c(l) * sum
+        }).collect()
+    }
+
+    fn assert_coefficients_close(coefficients : &DMatrix<f32>, expected : &[f64], epsilon : f64) {
+        for (index, (coefficient, expected)) in coefficients.iter().zip(expected.iter()).enumerate() {
+            assert!((*coefficient as f64 - expected).abs() < epsilon,
+                    "coefficient {} is {}, expected {}", index, coefficient, expected);
+        }
+    }
+
+    #[test]
+    fn calculate_dct_close_to_double_precision_dct() -> anyhow::Result<()> {
+        let pixels = [
+            144, 139, 149, 155, 153, 155, 155, 155,
+            151, 151, 151, 159, 156, 156, 156, 158,
+            151, 156, 160, 162, 159, 151, 151, 151,
+            158, 163, 161, 160, 160, 160, 160, 161,
+            158, 160, 161, 162, 160, 155, 155, 156,
+            161, 161, 161, 161, 160, 157, 157, 157,
+            162, 162, 161, 160, 161, 157, 157, 157,
+            162, 162, 161, 160, 163, 157, 158, 154];
+
+        let coefficients = calc_dct_coefficients(&Image::from_luma(&pixels, 8)?, &calc_dct_basis(8));
+
+        // The DC coefficient is close to 1258, where single precision floats are 1.2e-4 apart
+        assert_coefficients_close(&coefficients, &reference_dct(&pixels, 8), 1e-3);
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_dct_is_same_with_serial_dct() -> anyhow::Result<()> {
+        let pixels : Vec<u8> = (0..64 * 64).map(|i| ((i * 7 + i / 64 * 13) % 256) as u8).collect();
+        let image = Image::from_luma(&pixels, 64)?;
+        let dct_basis = calc_dct_basis(64);
+
+        let coefficients = calc_dct_coefficients(&image, &dct_basis);
+        let parallel_coefficients = calc_dct_coefficients_parallel(&image, &dct_basis);
+
+        assert_eq!(coefficients, parallel_coefficients);
+        Ok(())
+    }
+
+    #[test]
+    fn slice_dct_is_same_with_matrix_dct() -> anyhow::Result<()> {
+        let pixels = [
+            144, 139, 149, 155, 153, 155, 155, 155,
+            151, 151, 151, 159, 156, 156, 156, 158,
+            151, 156, 160, 162, 159, 151, 151, 151,
+            158, 163, 161, 160, 160, 160, 160, 161,
+            158, 160, 161, 162, 160, 155, 155, 156,
+            161, 161, 161, 161, 160, 157, 157, 157,
+            162, 162, 161, 160, 161, 157, 157, 157,
+            162, 162, 161, 160, 163, 157, 158, 154];
+        let image = Image::from_luma(&pixels, 8)?;
+        let float_pixels : Vec<f32> = pixels.iter().map(|p| *p as f32).collect();
+
+        let coefficients = calc_dct_coefficients(&image, &calc_dct_basis(8));
+        let slice_coefficients = core::dct_2d(&float_pixels, 8);
+
+        let are_same = coefficients.iter().zip(slice_coefficients.iter()).all(|(c1, c2)| (c1 - c2).abs() < 0.001);
+        assert!(are_same);
+
+        let (bits, threshold) = reduce_dct_coefficients(&coefficients, &core::block_positions(4, 4), ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?;
+        let (slice_bits, slice_threshold) = core::reduce_coefficients(&slice_coefficients, 8, 4, 0.0, TieBreaking::StrictlyAbove);
+        assert_eq!(bits, slice_bits);
+        assert!((threshold - slice_threshold).abs() < 0.001);
+        assert_eq!(hash_coefficients(&bits)?, core::pack_bits(&slice_bits).unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn reduce_coefficients_near_average_to_zero_when_tolerance_is_set() -> anyhow::Result<()> {
+        let coefficients = DMatrix::from_row_slice(3, 3, &[
+            100.0, 10.001, -5.0,
+            9.999, 10.0, 25.0,
+            20.0, 10.002, 9.998]);
+        // Tiny differences, such as the ones introduced by re-encoding
+        let perturbed_coefficients = coefficients.map(|c| if (c - 10.0f32).abs() < 0.01 { 20.0 - c } else { c });
+
+        let positions = core::block_positions(3, 3);
+
+        let hash = hash_coefficients(&reduce_dct_coefficients(&coefficients, &positions, ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?.0)?;
+        let perturbed_hash = hash_coefficients(&reduce_dct_coefficients(&perturbed_coefficients, &positions, ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?.0)?;
+        assert_ne!(hash, perturbed_hash);
+
+        let hash = hash_coefficients(&reduce_dct_coefficients(&coefficients, &positions, ThresholdStrategy::Mean, 0.1, TieBreaking::StrictlyAbove)?.0)?;
+        let perturbed_hash = hash_coefficients(&reduce_dct_coefficients(&perturbed_coefficients, &positions, ThresholdStrategy::Mean, 0.1, TieBreaking::StrictlyAbove)?.0)?;
+        assert_eq!(hash, perturbed_hash);
+        Ok(())
+    }
+
+    #[test]
+    fn return_average_of_reduced_coefficients_as_threshold() -> anyhow::Result<()> {
+        let coefficients = DMatrix::from_row_slice(3, 3, &[
+            1000.0, 4.0, -2.0,
+            6.0, 1.0, 30.0,
+            -8.0, 5.0, 50.0]);
+
+        let (bits, threshold) = reduce_dct_coefficients(&coefficients, &core::block_positions(2, 2), ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?;
+
+        // DC term is excluded, so the average is (0 + 4 + 6 + 1) / 4
+        assert_eq!(threshold, 2.75);
+        assert_eq!(bits, [0, 1, 1, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn return_median_of_reduced_coefficients_as_threshold() -> anyhow::Result<()> {
+        let coefficients = DMatrix::from_row_slice(3, 3, &[
+            1000.0, 4.0, -2.0,
+            60.0, 1.0, 30.0,
+            -8.0, 5.0, 50.0]);
+
+        let (bits, threshold) = reduce_dct_coefficients(&coefficients, &core::block_positions(2, 2), ThresholdStrategy::Median, 0.0, TieBreaking::StrictlyAbove)?;
+
+        // DC term is excluded, so the median is the middle one of 1, 4 and 60, unaffected by the outlier 60,
+        // and the bit of the DC term is always 0
+        assert_eq!(threshold, 4.0);
+        assert_eq!(bits, [0, 1, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn reduce_coefficients_to_rectangular_region() -> anyhow::Result<()> {
+        let mut coefficients = DMatrix::from_fn(16, 16, |k, l| if (k + l) % 2 == 0 { 1.0 } else { -1.0 });
+        coefficients[(0, 0)] = 1000.0;
+
+        let (bits, _) = reduce_dct_coefficients(&coefficients, &core::block_positions(4, 16), ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?;
+        let hash = hash_coefficients(&bits)?;
+
+        assert_eq!(bits.len(), 64);
+        assert_eq!(hash, 0xA5A5A5A5A5A5A5A5);
+        Ok(())
+    }
+
+    #[test]
+    fn keep_lowest_frequencies_in_zigzag_order() -> anyhow::Result<()> {
+        // Each coefficient is above the average when its frequencies sum to an even number
+        let coefficients = DMatrix::from_fn(32, 32, |k, l| if (k + l) % 2 == 0 { 1.0 } else { -0.5 });
+        let positions = core::zigzag_positions(40, 40)[..40].to_vec();
+
+        let (bits, _) = reduce_dct_coefficients(&coefficients, &positions, ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove)?;
+
+        // 40 coefficients are the first 8 anti-diagonals (36 coefficients) and 4 of the 9th
+        assert_eq!(bits.len(), 40);
+        assert!(positions.iter().all(|(k, l)| k + l <= 8));
+        assert_eq!(positions.iter().filter(|(k, l)| k + l == 8).count(), 4);
+        let expected_bits : Vec<u8> = positions.iter().
+            map(|&(k, l)| if (k, l) != (0, 0) && (k + l) % 2 == 0 { 1 } else { 0 }).
+            collect();
+        assert_eq!(bits, expected_bits);
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_when_average_of_coefficients_is_not_finite() -> anyhow::Result<()> {
+        let mut coefficients = DMatrix::from_element(4, 4, 1.0);
+        coefficients[(1, 2)] = f32::NAN;
+
+        let result = reduce_dct_coefficients(&coefficients, &core::block_positions(4, 4), ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove);
+        let empty_result = reduce_dct_coefficients(&coefficients, &[], ThresholdStrategy::Mean, 0.0, TieBreaking::StrictlyAbove);
+
+        assert!(matches!(result, Err(ImgcmpError::InvalidCoefficients(_))));
+        assert!(matches!(empty_result, Err(ImgcmpError::InvalidCoefficients(_))));
+        Ok(())
+    }
+
     #[test]
     fn calculate_hash_from_matrix() -> anyhow::Result<()> {
         let coefficients = DMatrix::from_row_slice(3, 3, &[
@@ -112,19 +440,32 @@ mod tests {
             1, 1, 1,
             1, 0, 0]);
 
-        let hash = hash_coefficients(&coefficients)?;
+        let hash = hash_coefficients(coefficients.as_slice())?;
 
         assert_eq!(hash, 0b010011110);
         Ok(())
     }
 
+    #[test]
+    fn calculate_hash_from_slice() -> anyhow::Result<()> {
+        // Same bits as `calculate_hash_from_matrix`, in the column-major order of the matrix
+        let bits = [
+            0, 1, 1,
+            1, 1, 0,
+            0, 1, 0];
+
+        assert_eq!(hash_bits(&bits)?, 0b010011110);
+        assert_eq!(hash_bits(&[1; 65]), Err(ImgcmpError::HashTooLarge { bits : 65, max_bits : 64 }));
+        Ok(())
+    }
+
     #[test]
     fn do_not_calculate_hash_when_matrix_dimension_is_greater_than_allowed() -> anyhow::Result<()> {
-        let coefficients = DMatrix::zeros(9, 9);
+        let coefficients = DMatrix::<u8>::zeros(9, 9);
 
-        let result = hash_coefficients(&coefficients);
+        let result = hash_coefficients(coefficients.as_slice());
 
-        assert!(result.is_err());
+        assert_eq!(result, Err(ImgcmpError::HashTooLarge { bits : 81, max_bits : 64 }));
         Ok(())
     }
 
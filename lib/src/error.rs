@@ -0,0 +1,84 @@
+use std::fmt;
+
+/// Errors returned by the library
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum ImgcmpError {
+    /// Passed dimensions or parameters cannot describe a valid image
+    InvalidDimensions(String),
+    /// Length of the passed buffer does not match the passed dimensions
+    BufferLengthMismatch { expected : usize, actual : usize },
+    /// A pixel does not have the number of channels declared by its image
+    ChannelCountMismatch { expected : usize, actual : usize },
+    /// A channel index is not smaller than the number of channels of the image
+    InvalidChannel { channel : usize, channels_per_pixel : usize },
+    /// DCT coefficients cannot be converted to bits, e.g. because they contain NaN
+    InvalidCoefficients(String),
+    /// Hash would need more bits than the ones available
+    HashTooLarge { bits : usize, max_bits : usize },
+    /// Image cannot be scaled to the passed dimensions
+    ScaleError { width : u32, height : u32 },
+    /// Configuration is inconsistent, e.g. tolerates more differing bits than hashes have
+    InvalidConfig(String),
+    /// Image is smaller than the configured minimum source size
+    ImageTooSmall { width : u32, height : u32, min_size : u32 },
+    /// Reading or writing a file failed
+    Io(String),
+    /// Encoded image bytes could not be decoded
+    Decode(String),
+    /// A hash cache could not be serialized or deserialized
+    InvalidCache(String)
+}
+
+pub type Result<T> = std::result::Result<T, ImgcmpError>;
+
+impl fmt::Display for ImgcmpError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImgcmpError::InvalidDimensions(reason) =>
+                write!(f, "Invalid dimensions: {}", reason),
+            ImgcmpError::BufferLengthMismatch { expected, actual } =>
+                write!(f, "Buffer should contain {} bytes but contains {}", expected, actual),
+            ImgcmpError::ChannelCountMismatch { expected, actual } =>
+                write!(f, "Pixel should have {} channels but has {}", expected, actual),
+            ImgcmpError::InvalidChannel { channel, channels_per_pixel } =>
+                write!(f, "Channel {} does not exist in image of {} channels", channel, channels_per_pixel),
+            ImgcmpError::InvalidCoefficients(reason) =>
+                write!(f, "Invalid DCT coefficients: {}", reason),
+            ImgcmpError::HashTooLarge { bits, max_bits } =>
+                write!(f, "Hash of {} bits is not allowed, maximum is {} bits", bits, max_bits),
+            ImgcmpError::ScaleError { width, height } =>
+                write!(f, "Cannot scale image to {}x{}", width, height),
+            ImgcmpError::InvalidConfig(reason) =>
+                write!(f, "Invalid configuration: {}", reason),
+            ImgcmpError::ImageTooSmall { width, height, min_size } =>
+                write!(f, "Image of {}x{} is smaller than the minimum size of {}", width, height, min_size),
+            ImgcmpError::Io(reason) =>
+                write!(f, "I/O error: {}", reason),
+            ImgcmpError::Decode(reason) =>
+                write!(f, "Cannot decode image: {}", reason),
+            ImgcmpError::InvalidCache(reason) =>
+                write!(f, "Invalid hash cache: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for ImgcmpError {}
+
+impl From<std::io::Error> for ImgcmpError {
+    fn from(error : std::io::Error) -> ImgcmpError {
+        ImgcmpError::Io(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_to_anyhow_error() {
+        let error : anyhow::Error = ImgcmpError::ScaleError { width : 0, height : 1 }.into();
+
+        assert_eq!(error.downcast_ref::<ImgcmpError>(), Some(&ImgcmpError::ScaleError { width : 0, height : 1 }));
+        assert_eq!(error.to_string(), "Cannot scale image to 0x1");
+    }
+}
@@ -0,0 +1,91 @@
+/// Value of the EXIF orientation tag of images stored the way they are displayed
+pub const NORMAL_ORIENTATION : u16 = 1;
+
+const ORIENTATION_TAG : u16 = 0x0112;
+const SHORT_TYPE : u16 = 3;
+
+/// Reads the EXIF orientation tag of an encoded JPEG image.
+/// Returns `None` for other formats, for images without EXIF data and for malformed EXIF data.
+pub fn orientation(bytes : &[u8]) -> Option<u16> {
+    if bytes.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut offset = 2;
+    while offset + 4 <= bytes.len() && bytes[offset] == 0xFF {
+        let marker = bytes[offset + 1];
+        // The start of scan segment is followed by the compressed image data, no EXIF data may follow
+        if marker == 0xDA {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let segment = bytes.get(offset + 4..offset + 2 + length)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+/// Reads the orientation tag from the first IFD of TIFF structured data, the payload of EXIF segments
+fn tiff_orientation(tiff : &[u8]) -> Option<u16> {
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None
+    };
+    let u16_at = |offset : usize| tiff.get(offset..offset + 2).map(|bytes| {
+        let bytes = [bytes[0], bytes[1]];
+        if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    });
+    let u32_at = |offset : usize| tiff.get(offset..offset + 4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    });
+
+    if u16_at(2)? != 42 {
+        return None;
+    }
+
+    let ifd_offset = u32_at(4)? as usize;
+    let entry_count = u16_at(ifd_offset)? as usize;
+    (0..entry_count).map(|entry| ifd_offset + 2 + entry * 12).
+        find(|&entry_offset| u16_at(entry_offset) == Some(ORIENTATION_TAG)).
+        filter(|&entry_offset| u16_at(entry_offset + 2) == Some(SHORT_TYPE)).
+        and_then(|entry_offset| u16_at(entry_offset + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg_with_exif(tiff : &[u8]) -> Vec<u8> {
+        let mut bytes = vec!(0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xE1);
+        bytes.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        bytes.extend_from_slice(b"Exif\0\0");
+        bytes.extend_from_slice(tiff);
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        bytes
+    }
+
+    #[test]
+    fn read_orientation_of_both_byte_orders() {
+        let big_endian = [b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0];
+        let little_endian = [b'I', b'I', 42, 0, 8, 0, 0, 0, 1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(orientation(&jpeg_with_exif(&big_endian)), Some(6));
+        assert_eq!(orientation(&jpeg_with_exif(&little_endian)), Some(8));
+    }
+
+    #[test]
+    fn return_none_without_orientation() {
+        let without_orientation = [b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x10, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(orientation(&jpeg_with_exif(&without_orientation)), None);
+        assert_eq!(orientation(&jpeg_with_exif(&[b'M', b'M', 0, 42, 0, 0])), None);
+        assert_eq!(orientation(&[0x89, b'P', b'N', b'G']), None);
+        assert_eq!(orientation(&[]), None);
+    }
+}
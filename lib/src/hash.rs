@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+
+/// Perceptual hash of an image. With the default `CoefficientOrder::Block`, bit `i` of the hash corresponds
+/// to the horizontal frequency `i % n` and the vertical frequency `i / n` of the reduced `n`x`n` DCT coefficients.
+/// Hashes are ordered by their numeric value, so that they can be sorted or used as keys of maps.
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    /// Hamming distance between this and the passed hash
+    pub fn distance(&self, other : &ImageHash) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    /// Number of bits set in the hash. Hashes with very few or very many set bits usually
+    /// belong to near-uniform images.
+    pub fn ones(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// The `k` hashes closest to this hash, as their indices in `others` along with their distances, sorted by
+    /// ascending distance, with ties in the order of `others`. Every hash is compared, so this suits small sets.
+    pub fn hamming_to_many(&self, others : &[ImageHash], k : usize) -> Vec<(usize, u32)> {
+        let mut distances : Vec<(usize, u32)> = others.iter().enumerate().
+            map(|(index, other)| (index, self.distance(other))).
+            collect();
+        distances.sort_by_key(|(_, distance)| *distance);
+        distances.truncate(k);
+        distances
+    }
+}
+
+/// How the distance between two hashes is measured
+#[derive (Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum DistanceMetric {
+    /// Number of differing bits, hashes are the same when it does not exceed `Config::allowed_distance`
+    Hamming,
+    /// Number of differing bits divided by the number of bits of the hash, so that thresholds are
+    /// comparable across hash sizes. Hashes are the same when it does not exceed `max_distance`.
+    NormalizedHamming { max_distance : f32 }
+}
+
+impl DistanceMetric {
+    /// Distance between two hashes of `bits` bits
+    pub fn distance(self, left : &ImageHash, right : &ImageHash, bits : u32) -> f32 {
+        let hamming_distance = left.distance(right) as f32;
+        match self {
+            DistanceMetric::Hamming => hamming_distance,
+            DistanceMetric::NormalizedHamming { .. } => hamming_distance / bits as f32
+        }
+    }
+}
+
+/// Returns the indices of the bits that differ between the passed hashes, in ascending order.
+pub fn diff_bits(a : &ImageHash, b : &ImageHash) -> Vec<u32> {
+    let xor = a.0 ^ b.0;
+    (0..64).filter(|index| xor & (1 << index) != 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn return_hamming_distance_between_hashes() {
+        assert_eq!(ImageHash(0b1011100100).distance(&ImageHash(0b1011100100)), 0);
+        assert_eq!(ImageHash(0b1101101100).distance(&ImageHash(0b1011100100)), 3);
+        assert_eq!(ImageHash(0).distance(&ImageHash(u64::MAX)), 64);
+    }
+
+    #[test]
+    fn return_closest_hashes_sorted_by_distance() {
+        let hashes = [ImageHash(0b1111), ImageHash(0b0001), ImageHash(0b0111), ImageHash(0b0000), ImageHash(0b0011)];
+
+        assert_eq!(ImageHash(0b0001).hamming_to_many(&hashes, 2), [(1, 0), (3, 1)]);
+        assert_eq!(ImageHash(0b0001).hamming_to_many(&hashes, 3), [(1, 0), (3, 1), (4, 1)]);
+        assert_eq!(ImageHash(0).hamming_to_many(&hashes[..1], 5), [(0, 4)]);
+    }
+
+    #[test]
+    fn return_number_of_set_bits() {
+        assert_eq!(ImageHash(0b1011100100).ones(), 5);
+        assert_eq!(ImageHash(0).ones(), 0);
+        assert_eq!(ImageHash(u64::MAX).ones(), 64);
+    }
+
+    #[test]
+    fn use_hashes_as_set_keys() {
+        extern crate std;
+        use std::collections::HashSet;
+
+        let hashes : HashSet<ImageHash> = [ImageHash(3), ImageHash(1), ImageHash(3)].iter().copied().collect();
+
+        assert_eq!(hashes.len(), 2);
+        assert!(hashes.contains(&ImageHash(1)));
+    }
+
+    #[test]
+    fn sort_hashes_by_value() {
+        let mut hashes = [ImageHash(u64::MAX), ImageHash(0b101), ImageHash(0), ImageHash(0b11)];
+
+        hashes.sort();
+
+        assert_eq!(hashes, [ImageHash(0), ImageHash(0b11), ImageHash(0b101), ImageHash(u64::MAX)]);
+    }
+
+    #[test]
+    fn normalize_hamming_distance_by_hash_bits() {
+        let (hash1, hash2) = (ImageHash(0b1101101100), ImageHash(0b1011100100));
+        let normalized = DistanceMetric::NormalizedHamming { max_distance : 0.1 };
+
+        assert_eq!(DistanceMetric::Hamming.distance(&hash1, &hash2, 64), 3.0);
+        assert_eq!(normalized.distance(&hash1, &hash2, 64), 3.0 / 64.0);
+        assert_eq!(normalized.distance(&hash1, &hash2, 16), 3.0 / 16.0);
+    }
+
+    #[test]
+    fn return_no_positions_for_equal_hashes() {
+        let hash = ImageHash(0b1011100100);
+
+        assert!(diff_bits(&hash, &hash).is_empty());
+    }
+
+    #[test]
+    fn return_positions_of_differing_bits() {
+        let hash1 = ImageHash(0b1101101100);
+        let hash2 = ImageHash(0b1011100100);
+
+        assert_eq!(diff_bits(&hash1, &hash2), [3, 7, 8]);
+        assert_eq!(diff_bits(&hash1, &ImageHash(hash1.0 | (1 << 63))), [63]);
+    }
+}
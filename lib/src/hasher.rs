@@ -0,0 +1,358 @@
+use crate::{dct, image_processing};
+use crate::{Config, DistanceMetric, Image, ImageHash, ImageSource, ImgcmpError, MatchClass, Result, SmallImagePolicy};
+use nalgebra::DMatrix;
+use std::borrow::Cow;
+use std::sync::Arc;
+use ndarray::Array2;
+
+/// Intermediate values computed while hashing an image, useful for debugging why
+/// two images produce different hashes.
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub struct HashDetails {
+    /// The final hash of the image
+    pub hash : ImageHash,
+    /// The mean or median of the reduced DCT coefficients, used as a threshold for converting them to bits
+    pub threshold : f32
+}
+
+/// Number of differing bits between two hashes per frequency band, useful for debugging whether
+/// two images differ in their global structure or in their fine details. Bands split the range of
+/// the sums of the horizontal and vertical frequencies of the coefficients in three equal parts.
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DistanceBreakdown {
+    /// Differing bits of the lowest third of the frequencies, i.e. of the global structure of the images
+    pub low_frequency : u32,
+    /// Differing bits of the middle third of the frequencies
+    pub mid_frequency : u32,
+    /// Differing bits of the highest third of the frequencies, i.e. of the fine details of the images
+    pub high_frequency : u32
+}
+
+impl DistanceBreakdown {
+    /// Number of differing bits over all bands
+    pub fn total(&self) -> u32 {
+        self.low_frequency + self.mid_frequency + self.high_frequency
+    }
+}
+
+/// Hashes images based on the DCT of their shrank grayscale version. The DCT basis is
+/// calculated once, so the same hasher should be reused for hashing many images.
+/// Bases of dimensions up to 32 are cached and shared between hashers, so that creating hashers is cheap too.
+pub struct PerceptualHasher {
+    config : Config,
+    dct_basis : Arc<Array2<DMatrix<f32>>>,
+    coefficient_positions : Vec<(usize, usize)>,
+    /// Bits of the hashes taken into account when comparing them
+    comparison_mask : u64,
+    /// Bits of the hashes from the lowest to the highest DCT frequency
+    frequency_order : Vec<usize>
+}
+
+impl PerceptualHasher {
+    /// Creates a hasher of the passed configuration without validating it, see `try_new`.
+    /// Configurations rejected by `Config::validate` may fail to hash images or panic.
+    pub fn new(config : Config) -> PerceptualHasher {
+        let dct_basis = dct::cached_dct_basis(config.dct_dimension);
+        let coefficient_positions = config.coefficient_positions();
+        let cutoff = config.low_freq_only.map_or(usize::MAX, |cutoff| cutoff as usize);
+        let comparison_mask = coefficient_positions.iter().enumerate().
+            filter(|(bit, (horizontal, vertical))| *bit < 64 && *horizontal < cutoff && *vertical < cutoff).
+            fold(0_u64, |mask, (bit, _)| mask | (1 << bit));
+        let mut frequency_order : Vec<usize> = (0..coefficient_positions.len().min(64)).collect();
+        frequency_order.sort_by_key(|bit| {
+            let (horizontal, vertical) = coefficient_positions[*bit];
+            (horizontal + vertical, vertical)
+        });
+        PerceptualHasher { config, dct_basis, coefficient_positions, comparison_mask, frequency_order }
+    }
+
+    /// Creates a hasher of the passed configuration, failing when `Config::validate` rejects it.
+    pub fn try_new(config : Config) -> Result<PerceptualHasher> {
+        config.validate()?;
+        Ok(PerceptualHasher::new(config))
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Calculates the hash of the passed image.
+    pub fn hash(&self, image : &Image) -> Result<ImageHash> {
+        let details = self.hash_details(image)?;
+        Ok(details.hash)
+    }
+
+    /// Loads, decodes and hashes the image file at `path`. See `Image::load`.
+    #[cfg(feature = "image")]
+    pub fn hash_file<P : AsRef<std::path::Path>>(&self, path : P) -> Result<ImageHash> {
+        self.hash(&Image::load(path)?)
+    }
+
+    /// Calculates the hash of the passed image, along with the intermediate values used to create it.
+    pub fn hash_details(&self, image : &Image) -> Result<HashDetails> {
+        self.check_source_size(image)?;
+        let trimmed_image = self.trim(image);
+        let image = self.composite(trimmed_image.as_ref())?;
+        let image = image.as_ref();
+        let dct_dimension = self.config.dct_dimension;
+        if self.config.blur_sigma.is_none() && !self.config.equalize_histogram && self.is_preprocessed(image) {
+            return self.preprocessed_hash_details(image);
+        }
+
+        log::debug!("Hashing {}x{} image of {} channels, scaling it to {}x{}", image.get_width(), image.get_height(),
+                    image.get_channels_per_pixel(), dct_dimension, dct_dimension);
+        let upscale_interpolation = self.config.upscale_interpolation;
+        let rounding = self.config.rounding_mode;
+        let shrank_grayscale_image = if self.config.blur_sigma.is_some() || self.config.equalize_histogram {
+            // Blur before scaling down, so that high frequency noise does not alias, and equalize
+            // the histogram of all pixels rather than the one of their averages
+            let (color_image, alpha) = self.split_alpha(image)?;
+            let mut grayscale_image = image_processing::into_grayscale_with(color_image.into_owned(), self.config.grayscale_mode, rounding)?;
+            if self.config.equalize_histogram {
+                grayscale_image = grayscale_image.equalize();
+            }
+            if let Some(sigma) = self.config.blur_sigma {
+                grayscale_image = image_processing::gaussian_blur(&grayscale_image, sigma)?;
+            }
+            match alpha {
+                Some(alpha) => image_processing::scale_image_alpha_weighted(&attach_alpha(&grayscale_image, &alpha), None,
+                                                                            dct_dimension, dct_dimension, rounding)?,
+                None => image_processing::scale_image_with(&grayscale_image, dct_dimension, dct_dimension, upscale_interpolation,
+                                                           self.config.downscale_mode, rounding)?
+            }
+        } else {
+            // Scale down to DCT size and convert to grayscale
+            let shrank_image = if self.is_alpha_weighted(image) {
+                image_processing::scale_image_alpha_weighted(image, None, dct_dimension, dct_dimension, rounding)?
+            } else {
+                image_processing::scale_image_with(image, dct_dimension, dct_dimension, upscale_interpolation, self.config.downscale_mode, rounding)?
+            };
+            image_processing::into_grayscale_with(shrank_image, self.config.grayscale_mode, rounding)?
+        };
+        log::trace!("Converted image to grayscale using {:?} mode", self.config.grayscale_mode);
+        self.preprocessed_hash_details(&shrank_grayscale_image)
+    }
+
+    /// Calculates the hash of any image source, e.g. of an `ImageView`, like the hash of an owned copy of it.
+    /// Unless border trimming, blurring, equalization, compositing or alpha weighting are configured, which need
+    /// a full resolution copy, the source is scaled down without copying its pixels.
+    pub fn hash_source<S : ImageSource + ?Sized>(&self, source : &S) -> Result<ImageHash> {
+        let config = &self.config;
+        if config.trim_border.is_some() || config.blur_sigma.is_some() || config.equalize_histogram ||
+            config.composite_background.is_some() || config.alpha_weighting {
+            return self.hash(&source.to_image()?);
+        }
+
+        self.check_source_size(source)?;
+        let dct_dimension = config.dct_dimension;
+        let shrank_image = image_processing::scale_image_with(source, dct_dimension, dct_dimension,
+                                                              config.upscale_interpolation, config.downscale_mode, config.rounding_mode)?;
+        let shrank_grayscale_image = image_processing::into_grayscale_with(shrank_image, config.grayscale_mode, config.rounding_mode)?;
+        let details = self.preprocessed_hash_details(&shrank_grayscale_image)?;
+        Ok(details.hash)
+    }
+
+    /// Calculates the hash of the passed image, ignoring the pixels flagged in `mask`, e.g. regions
+    /// of timestamps or logos. The mask has one flag per pixel, in row-major order. Blurring is not
+    /// applied on masked hashes, since it would spread masked pixels into their neighbours, and borders
+    /// are not trimmed, since the mask covers the whole image.
+    pub fn hash_masked(&self, image : &Image, mask : Option<&[bool]>) -> Result<ImageHash> {
+        let mask = match mask {
+            Some(mask) => mask,
+            None => return self.hash(image)
+        };
+        self.check_source_size(image)?;
+
+        let dct_dimension = self.config.dct_dimension;
+        let rounding = self.config.rounding_mode;
+        let image = self.composite(image)?;
+        let (color_image, alpha) = self.split_alpha(&image)?;
+        let mut grayscale_image = image_processing::into_grayscale_with(color_image.into_owned(), self.config.grayscale_mode, rounding)?;
+        if self.config.equalize_histogram {
+            grayscale_image = grayscale_image.equalize();
+        }
+        let shrank_grayscale_image = match alpha {
+            Some(alpha) => image_processing::scale_image_alpha_weighted(&attach_alpha(&grayscale_image, &alpha), Some(mask),
+                                                                        dct_dimension, dct_dimension, rounding)?,
+            None => image_processing::scale_image_masked(&grayscale_image, mask, dct_dimension, dct_dimension, rounding)?
+        };
+        let details = self.preprocessed_hash_details(&shrank_grayscale_image)?;
+        Ok(details.hash)
+    }
+
+    /// Calculates the hash of an image that is already scaled down to `dct_dimension`x`dct_dimension`
+    /// and converted to grayscale, skipping both steps. Other images are rejected.
+    pub fn hash_preprocessed(&self, gray_square : &Image) -> Result<ImageHash> {
+        if !self.is_preprocessed(gray_square) {
+            let dct_dimension = self.config.dct_dimension;
+            return Err(ImgcmpError::InvalidDimensions(
+                format!("Preprocessed image should be {}x{} with a single channel but is {}x{} with {} channels",
+                        dct_dimension, dct_dimension,
+                        gray_square.get_width(), gray_square.get_height(), gray_square.get_channels_per_pixel())));
+        }
+
+        let details = if self.config.equalize_histogram {
+            self.preprocessed_hash_details(&gray_square.equalize())?
+        } else {
+            self.preprocessed_hash_details(gray_square)?
+        };
+        Ok(details.hash)
+    }
+
+    /// Applies `Config::small_image_policy` on images smaller than `Config::min_source_size`
+    pub(crate) fn check_source_size<S : ImageSource + ?Sized>(&self, image : &S) -> Result<()> {
+        let min_size = match self.config.min_source_size {
+            Some(min_size) if image.get_width() < min_size || image.get_height() < min_size => min_size,
+            _ => return Ok(())
+        };
+
+        match self.config.small_image_policy {
+            SmallImagePolicy::Warn => {
+                log::warn!("Hashing image of {}x{}, smaller than {}x{}, its hash may be unreliable",
+                           image.get_width(), image.get_height(), min_size, min_size);
+                Ok(())
+            },
+            SmallImagePolicy::Reject =>
+                Err(ImgcmpError::ImageTooSmall { width : image.get_width(), height : image.get_height(), min_size })
+        }
+    }
+
+    fn trim<'a>(&self, image : &'a Image) -> Cow<'a, Image> {
+        match self.config.trim_border {
+            Some(tolerance) => Cow::Owned(image.trim_border(tolerance)),
+            None => Cow::Borrowed(image)
+        }
+    }
+
+    fn composite<'a>(&self, image : &'a Image) -> Result<Cow<'a, Image>> {
+        match self.config.composite_background {
+            Some(background) => Ok(Cow::Owned(image_processing::composite_onto(image, background)?)),
+            None => Ok(Cow::Borrowed(image))
+        }
+    }
+
+    /// Whether the colors of the image are averaged weighted by its alpha when scaled down, see `Config::alpha_weighting`
+    fn is_alpha_weighted(&self, image : &Image) -> bool {
+        self.config.alpha_weighting && matches!(image.get_channels_per_pixel(), 2 | 4)
+    }
+
+    /// Separates the colors of an alpha weighted image from its alpha, so that the colors can be converted
+    /// to grayscale on their own. Other images are returned unchanged, without alpha.
+    fn split_alpha<'a>(&self, image : &'a Image) -> Result<(Cow<'a, Image>, Option<Image>)> {
+        if !self.is_alpha_weighted(image) {
+            return Ok((Cow::Borrowed(image), None));
+        }
+
+        let alpha = image.get_channels_per_pixel() as usize - 1;
+        let color_image = image.map(alpha as u8, |pixel| pixel[..alpha].to_vec())?;
+        Ok((Cow::Owned(color_image), Some(image.channel(alpha)?)))
+    }
+
+    fn is_preprocessed(&self, image : &Image) -> bool {
+        let dct_dimension = self.config.dct_dimension;
+        image.get_width() == dct_dimension && image.get_height() == dct_dimension && image.get_channels_per_pixel() == 1
+    }
+
+    fn preprocessed_hash_details(&self, shrank_grayscale_image : &Image) -> Result<HashDetails> {
+        // compute NxN DCT coefficients
+        #[cfg(feature = "rayon")]
+        let dct_coefficients = dct::calc_dct_coefficients_parallel(shrank_grayscale_image, &self.dct_basis);
+        #[cfg(not(feature = "rayon"))]
+        let dct_coefficients = dct::calc_dct_coefficients(shrank_grayscale_image, &self.dct_basis);
+        let (dct_reduced_coefficients, threshold) = dct::reduce_dct_coefficients(&dct_coefficients,
+                                                                                 &self.coefficient_positions,
+                                                                                 self.config.threshold,
+                                                                                 self.config.threshold_tolerance,
+                                                                                 self.config.tie_breaking)?;
+        log::trace!("Threshold of DCT coefficients is {}", threshold);
+
+        // create hash
+        let hash = dct::hash_coefficients(&dct_reduced_coefficients)?;
+        log::debug!("Computed hash {:#018x}", hash);
+        Ok(HashDetails { hash : ImageHash(hash), threshold })
+    }
+
+    /// Checks whether the image has less variance than `Config::min_variance`, in which case its hash
+    /// should not be trusted, e.g. for solid color images, which all hash the same.
+    pub fn is_low_information(&self, image : &Image) -> bool {
+        image.variance() < self.config.min_variance
+    }
+
+    /// Checks whether the passed hashes are within the allowed distance of the configuration.
+    pub fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool {
+        match self.config.distance_metric {
+            DistanceMetric::Hamming =>
+                dct::compare_hashes(left_hash.0 & self.comparison_mask, right_hash.0 & self.comparison_mask) <= self.config.allowed_distance,
+            DistanceMetric::NormalizedHamming { max_distance } => self.distance(left_hash, right_hash) <= max_distance
+        }
+    }
+
+    /// Key for sorting or bucketing hashes by ranges of keys, made of the bits of the hash reordered so that
+    /// the lowest DCT frequencies occupy the most significant bits. The key is then dominated by the global
+    /// structure of the image, so hashes of similar images tend to have numerically close keys.
+    /// Keys are meant for bucketing only, distances between images should be measured on their hashes.
+    pub fn bucket_key(&self, hash : &ImageHash) -> u64 {
+        self.frequency_order.iter().enumerate().
+            fold(0, |key, (rank, bit)| key | ((hash.0 >> bit) & 1) << (63 - rank))
+    }
+
+    /// Classifies how close the passed hashes are, relative to the allowed distance of the configuration.
+    pub fn classify(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> MatchClass {
+        let distance = self.distance(left_hash, right_hash);
+        let allowed_distance = self.allowed_distance();
+
+        if distance == 0.0 {
+            MatchClass::Identical
+        } else if self.are_same(left_hash, right_hash) {
+            MatchClass::NearDuplicate
+        } else if distance <= 2.0 * allowed_distance {
+            MatchClass::Similar
+        } else {
+            MatchClass::Different
+        }
+    }
+
+    /// Maximum `distance` of hashes considered the same, in the unit of the distance metric of the configuration
+    pub(crate) fn allowed_distance(&self) -> f32 {
+        match self.config.distance_metric {
+            DistanceMetric::Hamming => self.config.allowed_distance as f32,
+            DistanceMetric::NormalizedHamming { max_distance } => max_distance
+        }
+    }
+
+    /// Counts the differing bits of the passed hashes per frequency band. Only the bits selected by
+    /// `Config::low_freq_only` are taken into account.
+    pub fn distance_breakdown(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> DistanceBreakdown {
+        let differing_bits = (left_hash.0 ^ right_hash.0) & self.comparison_mask;
+        let max_frequency = self.coefficient_positions.iter().map(|(horizontal, vertical)| horizontal + vertical).max().unwrap_or(0);
+
+        let mut breakdown = DistanceBreakdown::default();
+        for (bit, (horizontal, vertical)) in self.coefficient_positions.iter().enumerate().take(64) {
+            if differing_bits & (1 << bit) == 0 {
+                continue;
+            }
+            let frequency = horizontal + vertical;
+            if 3 * frequency <= max_frequency {
+                breakdown.low_frequency += 1;
+            } else if 3 * frequency <= 2 * max_frequency {
+                breakdown.mid_frequency += 1;
+            } else {
+                breakdown.high_frequency += 1;
+            }
+        }
+        breakdown
+    }
+
+    /// Distance between the passed hashes, measured with the distance metric of the configuration.
+    /// Only the bits selected by `Config::low_freq_only` are taken into account.
+    pub fn distance(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> f32 {
+        let mask = |hash : &ImageHash| ImageHash(hash.0 & self.comparison_mask);
+        self.config.distance_metric.distance(&mask(left_hash), &mask(right_hash), self.comparison_mask.count_ones())
+    }
+}
+
+/// Combines a grayscale image with the alpha of the image it was converted from, into a luma with alpha image
+fn attach_alpha(grayscale_image : &Image, alpha : &Image) -> Image {
+    Image::from_fn(grayscale_image.get_width(), grayscale_image.get_height(), 2,
+                   |x, y| vec!(grayscale_image.get_pixel(x, y)[0], alpha.get_pixel(x, y)[0]))
+}
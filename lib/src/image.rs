@@ -1,9 +1,12 @@
-use anyhow::{anyhow};
+use crate::error::{ImgcmpError, Result};
+use crate::image_processing::{self, GrayscaleMode, RoundingMode};
+#[cfg(feature = "image")]
+use crate::exif;
 
 type Pixel = Vec<u8>;
 
 /// Struct keeping data of a multi-channel image
-#[derive (Debug, PartialEq, Eq, Clone)]
+#[derive (Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Image {
     width : u32,
     height : u32,
@@ -12,27 +15,39 @@ pub struct Image {
 }
 
 impl Image {
-    /// Create an image from a byte buffer.
-    pub fn from(raw_image : &[u8], width : u32, channels_per_pixel : u8) -> anyhow::Result<Image> {
+    /// Create an image from a byte buffer. The buffer should hold complete rows of `width` pixels
+    /// of `channels_per_pixel` channels each.
+    pub fn from(raw_image : &[u8], width : u32, channels_per_pixel : u8) -> Result<Image> {
         if raw_image.is_empty() || width == 0 || channels_per_pixel == 0 {
-            return Err(anyhow!("Invalid parameters passed"));
+            return Err(ImgcmpError::InvalidDimensions("empty buffer, zero width or zero channels passed".to_string()));
         }
-        let num_pixels = raw_image.len() as u32 / channels_per_pixel as u32;
-        let height = (num_pixels / width) as u32;
-
-        let mut pixels = Vec::new();
-        for i in 0..num_pixels as usize {
-            let index = i * channels_per_pixel as usize;
-            let pixel = Pixel::from(&raw_image[index..index + channels_per_pixel as usize]);
-            pixels.push(pixel);
+        if !raw_image.len().is_multiple_of(channels_per_pixel as usize) {
+            return Err(ImgcmpError::InvalidDimensions(
+                format!("{} bytes cannot form complete pixels of {} channels", raw_image.len(), channels_per_pixel)));
         }
+        let height = Image::height_of(raw_image.len() / channels_per_pixel as usize, width)?;
+
+        let pixels = raw_image.chunks_exact(channels_per_pixel as usize).map(Pixel::from).collect();
+        let image = Image{width, height, channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
 
-        Ok(Image{width, height, channels_per_pixel, pixels})
+    /// Create an image from a buffer of 16 bit channels, keeping the most significant byte of each channel,
+    /// so that images promoted from 8 bit channels are converted back to the exact same image.
+    pub fn from_16bit(raw_image : &[u16], width : u32, channels_per_pixel : u8) -> Result<Image> {
+        let raw_image : Vec<u8> = raw_image.iter().map(|channel| (channel >> 8) as u8).collect();
+        Image::from(&raw_image, width, channels_per_pixel)
+    }
+
+    /// Create a single channel image from an array of luma values.
+    pub fn from_luma(raw_pixels : &[u8], width : u32) -> Result<Image> {
+        Image::from(raw_pixels, width, 1)
     }
 
     /// Create an image from an array of RGB tuples.
-    pub fn from_rgb(raw_pixels : &[(u8, u8, u8)], width : u32) -> anyhow::Result<Image> {
-        let height = raw_pixels.len() as u32 / width;
+    pub fn from_rgb(raw_pixels : &[(u8, u8, u8)], width : u32) -> Result<Image> {
+        let height = Image::height_of(raw_pixels.len(), width)?;
 
         let pixels = raw_pixels.iter().fold(Vec::new(), |mut acc, (r, g, b)| {
             acc.push(vec!(*r, *g, *b));
@@ -40,12 +55,13 @@ impl Image {
         });
 
         let image = Image {width, height, channels_per_pixel : 3, pixels};
+        image.validate()?;
         Ok(image)
     }
 
     /// Create an image from an array of RGBA tuples.
-    pub fn from_rgba(raw_pixels : &[(u8, u8, u8, u8)], width : u32) -> anyhow::Result<Image> {
-        let height = raw_pixels.len() as u32 / width;
+    pub fn from_rgba(raw_pixels : &[(u8, u8, u8, u8)], width : u32) -> Result<Image> {
+        let height = Image::height_of(raw_pixels.len(), width)?;
 
         let pixels = raw_pixels.iter().fold(Vec::new(), |mut acc, (r, g, b, a)| {
             acc.push(vec!(*r, *g, *b, *a));
@@ -53,17 +69,81 @@ impl Image {
         });
 
         let image = Image {width, height, channels_per_pixel : 4, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Create an image of the passed dimensions with the pixels returned by `f(x, y)`, e.g. for generating gradients.
+    /// Panics if `f` returns pixels of a different number of channels, or if a dimension or `channels_per_pixel` is zero.
+    pub fn from_fn<F>(width : u32, height : u32, channels_per_pixel : u8, mut f : F) -> Image where F: FnMut(u32, u32) -> Pixel {
+        assert!(width > 0 && height > 0, "Images should not be empty but {}x{} was passed", width, height);
+        let pixels = (0..height).
+            flat_map(|y| (0..width).map(move |x| (x, y))).
+            map(|(x, y)| f(x, y)).
+            collect();
+        let image = Image{width, height, channels_per_pixel, pixels};
+        if let Err(error) = image.validate() {
+            panic!("Invalid pixels generated: {}", error);
+        }
+        image
+    }
+
+    /// Number of rows of `pixel_count` pixels, which should form complete rows of `width` pixels
+    fn height_of(pixel_count : usize, width : u32) -> Result<u32> {
+        if width == 0 || pixel_count == 0 || !pixel_count.is_multiple_of(width as usize) {
+            return Err(ImgcmpError::InvalidDimensions(
+                format!("{} pixels cannot form complete rows of width {}", pixel_count, width)));
+        }
+        Ok((pixel_count / width as usize) as u32)
+    }
+
+    /// Create an image by converting each pixel of this image to a pixel of `channels_per_pixel` channels.
+    /// Returns an error if `f` returns pixels of a different number of channels.
+    pub fn map<F>(&self, channels_per_pixel : u8, f : F) -> Result<Image> where F: FnMut(&Pixel) -> Pixel {
+        let pixels = self.pixels.iter().map(f).collect();
+        let image = Image{width : self.width, height : self.height, channels_per_pixel, pixels};
+        image.validate()?;
         Ok(image)
     }
 
+    /// Check that the image is consistent, i.e. that all pixels have the declared number of channels.
+    pub fn validate(&self) -> Result<()> {
+        if self.channels_per_pixel == 0 {
+            return Err(ImgcmpError::InvalidDimensions("images should have at least one channel".to_string()));
+        }
+        self.assert_channels()
+    }
+
+    fn assert_channels(&self) -> Result<()> {
+        let expected = self.channels_per_pixel as usize;
+        match self.pixels.iter().find(|pixel| pixel.len() != expected) {
+            Some(pixel) => Err(ImgcmpError::ChannelCountMismatch { expected, actual : pixel.len() }),
+            None => Ok(())
+        }
+    }
+
+    /// Returns the pixel at (x, y). Panics if the coordinates are outside of the image.
     pub fn get_pixel(&self, x : u32, y : u32) -> &Pixel {
-        let index = y * self.width + x;
-        &self.pixels[index as usize]
+        let index = self.pixel_index(x, y);
+        &self.pixels[index]
+    }
+
+    /// Returns the pixel at fractional coordinates, interpolated bilinearly between the four nearest pixels,
+    /// e.g. for custom warping. Pixel centers lie on integer coordinates and coordinates outside of the image
+    /// are clamped to its edges.
+    pub fn sample_bilinear(&self, fx : f32, fy : f32) -> Vec<u8> {
+        image_processing::sample_bilinear(self, fx, fy)
     }
 
     fn access_pixel(&mut self, x : u32, y : u32) -> &mut Pixel {
-        let index = y * self.width + x;
-        &mut self.pixels[index as usize]
+        let index = self.pixel_index(x, y);
+        &mut self.pixels[index]
+    }
+
+    fn pixel_index(&self, x : u32, y : u32) -> usize {
+        assert!(x < self.width && y < self.height,
+                "Pixel ({}, {}) is outside of {}x{} image", x, y, self.width, self.height);
+        row_major_index(self.width, x, y)
     }
 
     pub fn get_width(&self) -> u32 {
@@ -78,8 +158,348 @@ impl Image {
         self.channels_per_pixel
     }
 
+    /// Number of pixels of the image, i.e. its width times its height.
+    pub fn pixels_len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// Whether the image has no pixels. Images are never empty, since all constructors reject zero dimensions,
+    /// so this is always false.
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// Create a new image from the rectangle of the passed dimensions, with its top left corner at (x, y).
+    pub fn crop(&self, x : u32, y : u32, width : u32, height : u32) -> Result<Image> {
+        self.view(x, y, width, height)?.to_image()
+    }
+
+    /// Borrow the rectangle of the passed dimensions, with its top left corner at (x, y), without copying its pixels.
+    pub fn view(&self, x : u32, y : u32, width : u32, height : u32) -> Result<ImageView<'_>> {
+        let is_outside = |start : u32, length : u32, image_length : u32| start.checked_add(length).is_none_or(|end| end > image_length);
+        if width == 0 || height == 0 || is_outside(x, width, self.width) || is_outside(y, height, self.height) {
+            return Err(ImgcmpError::InvalidDimensions("crop rectangle should be non empty and inside the image".to_string()));
+        }
+        Ok(ImageView { image : self, x, y, width, height })
+    }
+
+    /// Create a new image from the largest centered rectangle of the passed aspect ratio, e.g. 1:1 for a square.
+    pub fn crop_to_aspect(&self, ratio_width : u32, ratio_height : u32) -> Result<Image> {
+        if ratio_width == 0 || ratio_height == 0 {
+            return Err(ImgcmpError::InvalidDimensions("aspect ratio terms should be positive".to_string()));
+        }
+
+        // Compare width / height with ratio_width / ratio_height without rounding
+        let (width, height) = if self.width as u64 * ratio_height as u64 > self.height as u64 * ratio_width as u64 {
+            ((self.height as u64 * ratio_width as u64 / ratio_height as u64) as u32, self.height)
+        } else {
+            (self.width, (self.width as u64 * ratio_height as u64 / ratio_width as u64) as u32)
+        };
+        self.crop((self.width - width) / 2, (self.height - height) / 2, width.max(1), height.max(1))
+    }
+
+    /// Create a new image surrounded by borders of the passed widths, filled with the `fill` pixel,
+    /// e.g. for letterboxing an image before comparing it. `fill` should have as many channels as the image.
+    pub fn pad(&self, top : u32, bottom : u32, left : u32, right : u32, fill : &[u8]) -> Result<Image> {
+        if fill.len() != self.channels_per_pixel as usize {
+            return Err(ImgcmpError::ChannelCountMismatch { expected : self.channels_per_pixel as usize, actual : fill.len() });
+        }
+
+        let (width, height) = (left + self.width + right, top + self.height + bottom);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let inside = (left..left + self.width).contains(&x) && (top..top + self.height).contains(&y);
+                let pixel = if inside { self.get_pixel(x - left, y - top).clone() } else { Pixel::from(fill) };
+                pixels.push(pixel);
+            }
+        }
+
+        let image = Image{width, height, channels_per_pixel : self.channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Create an image with `other` placed to the right of this image, e.g. for viewing two images side by side.
+    /// Both images should have the same height and channels.
+    pub fn concatenate_horizontal(&self, other : &Image) -> Result<Image> {
+        if self.height != other.height {
+            return Err(ImgcmpError::InvalidDimensions(format!("images should have the same height but are {}x{} and {}x{}",
+                                                              self.width, self.height, other.width, other.height)));
+        }
+        self.assert_same_channels(other)?;
+
+        let pixels = self.pixels.chunks(self.width as usize).zip(other.pixels.chunks(other.width as usize)).
+            flat_map(|(left_row, right_row)| left_row.iter().chain(right_row.iter()).cloned()).
+            collect();
+        let image = Image{width : self.width + other.width, height : self.height, channels_per_pixel : self.channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Create an image with `other` placed below this image. Both images should have the same width and channels.
+    pub fn concatenate_vertical(&self, other : &Image) -> Result<Image> {
+        if self.width != other.width {
+            return Err(ImgcmpError::InvalidDimensions(format!("images should have the same width but are {}x{} and {}x{}",
+                                                              self.width, self.height, other.width, other.height)));
+        }
+        self.assert_same_channels(other)?;
+
+        let pixels = self.pixels.iter().chain(other.pixels.iter()).cloned().collect();
+        let image = Image{width : self.width, height : self.height + other.height, channels_per_pixel : self.channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Create a copy of the image without its uniform borders, e.g. the solid borders of scans or screenshots.
+    /// Rows and columns along the edges are removed while all their pixels are within `tolerance` of their
+    /// first pixel in every channel. Uniform images are returned unchanged.
+    pub fn trim_border(&self, tolerance : u8) -> Image {
+        let is_close = |left : &Pixel, right : &Pixel| left.iter().zip(right.iter()).all(|(l, r)| l.abs_diff(*r) <= tolerance);
+        let is_uniform_row = |y : u32| (0..self.width).all(|x| is_close(self.get_pixel(x, y), self.get_pixel(0, y)));
+
+        let top = match (0..self.height).find(|y| !is_uniform_row(*y)) {
+            Some(top) => top,
+            None => return self.clone()
+        };
+        let bottom = (top..self.height).rfind(|y| !is_uniform_row(*y)).unwrap_or(top);
+        let is_uniform_column = |x : u32| (top..=bottom).all(|y| is_close(self.get_pixel(x, y), self.get_pixel(x, top)));
+        let left = (0..self.width).find(|x| !is_uniform_column(*x)).unwrap_or(0);
+        let right = (left..self.width).rfind(|x| !is_uniform_column(*x)).unwrap_or(left);
+
+        self.crop(left, top, right - left + 1, bottom - top + 1).
+            expect("trimmed rectangle is non empty and inside the image")
+    }
+
+    /// Create a copy of the image scaled to the passed dimensions, averaging the covered pixels when
+    /// scaling down and interpolating bilinearly when scaling up.
+    pub fn scale(&self, width : u32, height : u32) -> Result<Image> {
+        image_processing::scale_image(self, width, height)
+    }
+
+    /// Create a copy of the image scaled down by an integer `factor`, averaging each disjoint block of
+    /// `factor`x`factor` pixels, e.g. 2 for scaling 64x64 images to 32x32. The factor should divide both dimensions.
+    pub fn sub_sample(&self, factor : u32) -> Result<Image> {
+        image_processing::sub_sample(self, factor, factor, RoundingMode::Floor)
+    }
+
+    /// Create a copy of the image rotated and flipped to be displayed as described by the passed EXIF
+    /// orientation tag, e.g. 6 for images stored rotated 90 degrees counter-clockwise.
+    /// Unknown orientation values leave the image unchanged.
+    pub fn orient(&self, orientation : u16) -> Image {
+        if !(2..=8).contains(&orientation) {
+            return self.clone();
+        }
+
+        let (width, height) = (self.width, self.height);
+        let source_coordinates = |x : u32, y : u32| match orientation {
+            2 => (width - 1 - x, y),
+            3 => (width - 1 - x, height - 1 - y),
+            4 => (x, height - 1 - y),
+            5 => (y, x),
+            6 => (y, height - 1 - x),
+            7 => (width - 1 - y, height - 1 - x),
+            _ => (width - 1 - y, x)
+        };
+        let (oriented_width, oriented_height) = if orientation >= 5 { (height, width) } else { (width, height) };
+
+        let pixels = (0..oriented_height).
+            flat_map(|y| (0..oriented_width).map(move |x| (x, y))).
+            map(|(x, y)| {
+                let (source_x, source_y) = source_coordinates(x, y);
+                self.get_pixel(source_x, source_y).clone()
+            }).
+            collect();
+        Image{width : oriented_width, height : oriented_height, channels_per_pixel : self.channels_per_pixel, pixels}
+    }
+
+    /// Convert the image to a single channel image, averaging the channels of each pixel.
+    /// Single channel images are returned unchanged, luma with alpha images keep their luma.
+    pub fn into_grayscale(self) -> Image {
+        image_processing::into_grayscale_with(self, GrayscaleMode::Average, RoundingMode::Floor).
+            expect("averaging channels always creates valid single channel pixels")
+    }
+
+    /// Create a single channel copy of the image, averaging the channels of each pixel. See `into_grayscale`.
+    pub fn grayscale(&self) -> Image {
+        self.clone().into_grayscale()
+    }
+
+    /// Create a preview of the image, scaled so that its longer edge is `max_edge` while preserving
+    /// its aspect ratio and channels.
+    pub fn thumbnail(&self, max_edge : u32) -> Result<Image> {
+        image_processing::scale_image_fit(self, max_edge)
+    }
+
+    /// Dimensions of the `thumbnail` whose longer edge is `max_edge`.
+    pub fn fit_dimensions(&self, max_edge : u32) -> (u32, u32) {
+        image_processing::fit_dimensions(self.width, self.height, max_edge)
+    }
+
+    /// Create a single channel image from the channel of the passed index, e.g. 1 for the green channel of an RGB image.
+    pub fn channel(&self, index : usize) -> Result<Image> {
+        let channels_per_pixel = self.channels_per_pixel as usize;
+        if index >= channels_per_pixel {
+            return Err(ImgcmpError::InvalidChannel { channel : index, channels_per_pixel });
+        }
+        self.map(1, |pixel| vec!(pixel[index]))
+    }
+
+    /// Create an image with the histogram of each channel equalized, so that its values spread over
+    /// the full range of a channel. Channels of a single value are kept unchanged.
+    pub fn equalize(&self) -> Image {
+        let mut image = self.clone();
+        let pixel_count = self.pixels.len() as u64;
+        for channel in 0..self.channels_per_pixel as usize {
+            let mut histogram = [0_u64; 256];
+            for pixel in &self.pixels {
+                histogram[pixel[channel] as usize] += 1;
+            }
+
+            let mut cumulative = [0_u64; 256];
+            let mut total = 0;
+            for (value, count) in histogram.iter().enumerate() {
+                total += count;
+                cumulative[value] = total;
+            }
+
+            let min_cumulative = cumulative[histogram.iter().position(|count| *count > 0).unwrap_or(0)];
+            if min_cumulative == pixel_count {
+                continue;
+            }
+            let range = (pixel_count - min_cumulative) as f64;
+            image.apply(|pixel| {
+                let equalized = (cumulative[pixel[channel] as usize] - min_cumulative) as f64 / range * u8::MAX as f64;
+                pixel[channel] = equalized.round() as u8;
+            });
+        }
+        image
+    }
+
+    /// Create an image with `delta` added to the color channels of each pixel, saturating at the
+    /// bounds of a channel instead of wrapping around. Alpha channels are kept unchanged.
+    pub fn adjust_brightness(&self, delta : i16) -> Image {
+        let color_channels = color_channel_count(self.channels_per_pixel);
+        let mut image = self.clone();
+        image.apply(|pixel| {
+            for channel in pixel.iter_mut().take(color_channels) {
+                *channel = add_to_channel(*channel, delta);
+            }
+        });
+        image
+    }
+
+    /// Create an image with the colors inverted, i.e. with each color channel mapped to `255 - value`.
+    /// Alpha channels are kept unchanged.
+    pub fn invert(&self) -> Image {
+        let color_channels = color_channel_count(self.channels_per_pixel);
+        let mut image = self.clone();
+        image.apply(|pixel| {
+            for channel in pixel.iter_mut().take(color_channels) {
+                *channel = u8::MAX - *channel;
+            }
+        });
+        image
+    }
+
+    /// Create an image with its brightness shifted so that its `mean_brightness` gets as close to
+    /// `target` as saturating the channels allows, e.g. to compare images taken under different exposure.
+    pub fn normalize_brightness(&self, target : u8) -> Image {
+        let delta = (target as f32 - self.mean_brightness()).round() as i16;
+        self.adjust_brightness(delta)
+    }
+
+    /// Average value over all channels of all pixels.
+    pub fn mean_brightness(&self) -> f32 {
+        let means = self.channel_means();
+        means.iter().sum::<f32>() / means.len() as f32
+    }
+
+    /// Average value of each channel over all pixels.
+    pub fn channel_means(&self) -> Vec<f32> {
+        let mut sums = vec!(0_u64; self.channels_per_pixel as usize);
+        for pixel in &self.pixels {
+            for (sum, channel) in sums.iter_mut().zip(pixel.iter()) {
+                *sum += *channel as u64;
+            }
+        }
+
+        sums.iter().map(|sum| (*sum as f64 / self.pixels.len() as f64) as f32).collect()
+    }
+
+    /// Average over the channels of the variance of each channel over all pixels. Solid color images have
+    /// zero variance, and images of very low variance carry too little information for reliable hashes.
+    pub fn variance(&self) -> f32 {
+        let means = self.channel_means();
+        let mut squared_deviations = vec!(0_f64; self.channels_per_pixel as usize);
+        for pixel in &self.pixels {
+            for ((sum, channel), mean) in squared_deviations.iter_mut().zip(pixel.iter()).zip(means.iter()) {
+                *sum += (*channel as f64 - *mean as f64).powi(2);
+            }
+        }
+
+        let pixel_count = self.pixels.len() as f64;
+        (squared_deviations.iter().map(|sum| sum / pixel_count).sum::<f64>() / squared_deviations.len() as f64) as f32
+    }
+
+    /// Compare two images by their visible content, so that an RGB image is considered equal
+    /// to its RGBA equivalent when all alpha values are opaque.
+    pub fn visually_equal(&self, other : &Image) -> bool {
+        if self.width != other.width || self.height != other.height {
+            return false;
+        }
+
+        self.pixels.iter().zip(other.pixels.iter()).
+            all(|(left, right)| pixels_visually_equal(left, right))
+    }
+
+    /// Create an image with `over` composited over this image by standard source-over alpha compositing,
+    /// e.g. for placing a transparent foreground on a background. Both images should be RGBA images of the same dimensions.
+    pub fn blend(&self, over : &Image) -> Result<Image> {
+        self.assert_same_shape(over)?;
+        if self.channels_per_pixel != 4 {
+            return Err(ImgcmpError::ChannelCountMismatch { expected : 4, actual : self.channels_per_pixel as usize });
+        }
+        Ok(image_processing::blend_over(self, over))
+    }
+
+    /// Create an image with the absolute difference of each channel of the two images, useful for
+    /// inspecting where two images differ. Both images should have the same dimensions and channels.
+    pub fn difference(&self, other : &Image) -> Result<Image> {
+        self.assert_same_shape(other)?;
+
+        let pixels = self.pixels.iter().zip(other.pixels.iter()).
+            map(|(left, right)| left.iter().zip(right.iter()).map(|(l, r)| channel_difference(*l, *r)).collect()).
+            collect();
+        let image = Image{width : self.width, height : self.height, channels_per_pixel : self.channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Export the image to a byte buffer, in the layout accepted by `Image::from`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    fn assert_same_shape(&self, other : &Image) -> Result<()> {
+        if self.width != other.width || self.height != other.height {
+            return Err(ImgcmpError::InvalidDimensions(format!("images should have the same dimensions but are {}x{} and {}x{}",
+                                                              self.width, self.height, other.width, other.height)));
+        }
+        self.assert_same_channels(other)
+    }
+
+    fn assert_same_channels(&self, other : &Image) -> Result<()> {
+        if self.channels_per_pixel != other.channels_per_pixel {
+            return Err(ImgcmpError::ChannelCountMismatch { expected : self.channels_per_pixel as usize,
+                                                           actual : other.channels_per_pixel as usize });
+        }
+        Ok(())
+    }
+
     /// Apply a function on each pixel of the image. Pixels are passed as mutable references
-    /// so that mutations can happen in place.
+    /// so that mutations can happen in place. Mutations should not change the number of channels,
+    /// use `map` for that.
     pub fn apply<F>(&mut self, mut f : F) where F: FnMut(&mut Pixel) {
         for y in 0..self.height {
             for x in 0..self.width {
@@ -88,4 +508,1161 @@ impl Image {
             }
         }
     }
+
+    /// Iterate over the rows of the image, from top to bottom, as mutable slices of `width` pixels
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [Pixel]> {
+        self.pixels.chunks_mut(self.width as usize)
+    }
+
+    /// Apply a function on each row of the image, from top to bottom, which is more efficient than
+    /// `apply` for separable filters. As with `apply`, mutations should not change the number of channels.
+    pub fn apply_row<F>(&mut self, f : F) where F: FnMut(&mut [Pixel]) {
+        self.rows_mut().for_each(f);
+    }
+}
+
+/// Read-only access to the pixels of an image, so that algorithms can process both owned images
+/// and views into them
+pub trait ImageSource {
+    fn get_width(&self) -> u32;
+
+    fn get_height(&self) -> u32;
+
+    fn get_channels_per_pixel(&self) -> u8;
+
+    /// Returns the pixel at (x, y). Panics if the coordinates are outside of the image.
+    fn get_pixel(&self, x : u32, y : u32) -> &[u8];
+
+    /// Copy the pixels into an owned image
+    fn to_image(&self) -> Result<Image> {
+        let width = self.get_width();
+        let bytes : Vec<u8> = (0..self.get_height()).
+            flat_map(|y| (0..width).flat_map(move |x| self.get_pixel(x, y).iter().copied())).
+            collect();
+        Image::from(&bytes, width, self.get_channels_per_pixel())
+    }
+
+    /// Convert into an owned image, copying the pixels only when they are not owned already
+    fn into_image(self) -> Result<Image> where Self : Sized {
+        self.to_image()
+    }
+}
+
+impl ImageSource for Image {
+    fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    fn get_channels_per_pixel(&self) -> u8 {
+        self.channels_per_pixel
+    }
+
+    fn get_pixel(&self, x : u32, y : u32) -> &[u8] {
+        Image::get_pixel(self, x, y)
+    }
+
+    fn to_image(&self) -> Result<Image> {
+        Ok(self.clone())
+    }
+
+    fn into_image(self) -> Result<Image> {
+        Ok(self)
+    }
+}
+
+/// Rectangle of an image borrowed without copying its pixels, created by `Image::view`
+#[derive (Debug, Clone, Copy)]
+pub struct ImageView<'a> {
+    image : &'a Image,
+    x : u32,
+    y : u32,
+    width : u32,
+    height : u32
+}
+
+impl<'a> ImageSource for ImageView<'a> {
+    fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    fn get_channels_per_pixel(&self) -> u8 {
+        self.image.channels_per_pixel
+    }
+
+    fn get_pixel(&self, x : u32, y : u32) -> &[u8] {
+        assert!(x < self.width && y < self.height,
+                "Pixel ({}, {}) is outside of {}x{} view", x, y, self.width, self.height);
+        self.image.get_pixel(self.x + x, self.y + y)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Image {
+    /// Create an image like `map`, converting the pixels in parallel
+    pub fn map_parallel<F>(&self, channels_per_pixel : u8, f : F) -> Result<Image> where F: Fn(&Pixel) -> Pixel + Sync + Send {
+        use rayon::prelude::*;
+
+        let pixels = self.pixels.par_iter().map(f).collect();
+        let image = Image{width : self.width, height : self.height, channels_per_pixel, pixels};
+        image.validate()?;
+        Ok(image)
+    }
+
+    /// Apply a function on each pixel of the image like `apply`, processing the pixels in parallel
+    pub fn apply_parallel<F>(&mut self, f : F) where F: Fn(&mut Pixel) + Sync + Send {
+        use rayon::prelude::*;
+
+        self.pixels.par_iter_mut().for_each(f);
+    }
+}
+
+#[cfg(feature = "image")]
+impl Image {
+    /// Decode an image from encoded bytes in any of the formats supported by the `image` crate,
+    /// e.g. the contents of a JPEG file received over the network.
+    /// JPEG images are rotated and flipped according to their EXIF orientation tag, see `orient`.
+    pub fn from_encoded(bytes : &[u8]) -> Result<Image> {
+        let dynamic_image = ::image::load_from_memory(bytes).
+            map_err(|error| ImgcmpError::Decode(error.to_string()))?;
+        let image = Image::from_dynamic_image(&dynamic_image)?;
+        Ok(match exif::orientation(bytes) {
+            Some(orientation) if orientation != exif::NORMAL_ORIENTATION => image.orient(orientation),
+            _ => image
+        })
+    }
+
+    /// Load and decode the image file at `path`. Errors mention the path of the file.
+    pub fn load<P : AsRef<std::path::Path>>(path : P) -> Result<Image> {
+        let path = path.as_ref();
+        let with_path = |reason : String| format!("{}: {}", path.display(), reason);
+        let bytes = std::fs::read(path).map_err(|error| ImgcmpError::Io(with_path(error.to_string())))?;
+        Image::from_encoded(&bytes).map_err(|error| match error {
+            ImgcmpError::Decode(reason) => ImgcmpError::Decode(with_path(reason)),
+            error => error
+        })
+    }
+
+    /// Convert an image of the `image` crate. Luma, luma with alpha, RGB and RGBA images keep their channels,
+    /// with 16 bit channels converted like `from_16bit`, other images are converted to RGBA.
+    /// Decoded images carry no EXIF data, so they are not reoriented; use `orient` with their orientation tag.
+    pub fn from_dynamic_image(dynamic_image : &::image::DynamicImage) -> Result<Image> {
+        use ::image::{ColorType, DynamicImage, GenericImageView};
+
+        let width = dynamic_image.width();
+        match dynamic_image {
+            DynamicImage::ImageLuma16(buffer) => return Image::from_16bit(buffer.as_raw(), width, 1),
+            DynamicImage::ImageLumaA16(buffer) => return Image::from_16bit(buffer.as_raw(), width, 2),
+            DynamicImage::ImageRgb16(buffer) => return Image::from_16bit(buffer.as_raw(), width, 3),
+            DynamicImage::ImageRgba16(buffer) => return Image::from_16bit(buffer.as_raw(), width, 4),
+            _ => {}
+        }
+
+        let converted_image;
+        let dynamic_image = match dynamic_image.color() {
+            ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => dynamic_image,
+            _ => {
+                converted_image = DynamicImage::ImageRgba8(dynamic_image.to_rgba8());
+                &converted_image
+            }
+        };
+        Image::from(dynamic_image.as_bytes(), dynamic_image.width(), dynamic_image.color().channel_count())
+    }
+
+    /// Convert the image to an image of the `image` crate, e.g. for encoding it to a file.
+    /// Images of 1, 2, 3 and 4 channels are converted to luma, luma with alpha, RGB and RGBA images respectively.
+    pub fn to_dynamic_image(&self) -> Result<::image::DynamicImage> {
+        use ::image::{DynamicImage, ImageBuffer};
+
+        let (width, height, bytes) = (self.width, self.height, self.to_bytes());
+        let buffer_error = || ImgcmpError::BufferLengthMismatch {
+            expected : width as usize * height as usize * self.channels_per_pixel as usize,
+            actual : self.pixels.len() * self.channels_per_pixel as usize
+        };
+        let dynamic_image = match self.channels_per_pixel {
+            1 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(buffer_error)?),
+            2 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(buffer_error)?),
+            3 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(buffer_error)?),
+            4 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, bytes).ok_or_else(buffer_error)?),
+            channels => return Err(ImgcmpError::InvalidDimensions(
+                format!("images of {} channels cannot be converted to dynamic images", channels)))
+        };
+        Ok(dynamic_image)
+    }
+}
+
+/// Computed in `usize`, since `y * width` overflows `u32` for images of more than 4 gigapixels
+fn row_major_index(width : u32, x : u32, y : u32) -> usize {
+    y as usize * width as usize + x as usize
+}
+
+/// Number of channels of a pixel that carry color, i.e. all but the alpha channel of luma with alpha and RGBA pixels
+fn color_channel_count(channels_per_pixel : u8) -> usize {
+    match channels_per_pixel {
+        2 | 4 => channels_per_pixel as usize - 1,
+        channels => channels as usize
+    }
+}
+
+/// Adds a possibly negative `delta` to a channel, saturating at 0 and 255 instead of wrapping around
+fn add_to_channel(channel : u8, delta : i16) -> u8 {
+    (channel as i16).saturating_add(delta).clamp(0, u8::MAX as i16) as u8
+}
+
+/// Absolute difference of two channels, which never wraps around regardless of their order
+fn channel_difference(left : u8, right : u8) -> u8 {
+    left.max(right) - left.min(right)
+}
+
+fn pixels_visually_equal(left : &Pixel, right : &Pixel) -> bool {
+    let is_opaque = |pixel : &Pixel| pixel.len() == 3 || pixel[3] == u8::MAX;
+    match (left.len(), right.len()) {
+        (l, r) if l == r => left == right,
+        (3, 4) | (4, 3) => left[..3] == right[..3] && is_opaque(left) && is_opaque(right),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod from {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn return_invalid_dimensions_for_invalid_parameters() {
+            assert!(matches!(Image::from(&[], 1, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from(&[1, 2], 0, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from(&[1, 2], 1, 0), Err(ImgcmpError::InvalidDimensions(_))));
+        }
+
+        #[test]
+        fn return_invalid_dimensions_for_incomplete_pixels_or_rows() {
+            assert!(matches!(Image::from(&[1, 2, 3, 4], 1, 3), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from(&[1, 2, 3], 2, 1), Err(ImgcmpError::InvalidDimensions(_))));
+        }
+
+        #[test]
+        fn do_not_create_images_of_zero_height() {
+            // A single pixel of width 2 used to create a 2x0 image holding a pixel, whose scaling panicked
+            let result = Image::from(&[1], 2, 1);
+
+            assert!(matches!(result, Err(ImgcmpError::InvalidDimensions(_))));
+        }
+    }
+
+    mod from_fn {
+        use crate::Image;
+
+        #[test]
+        fn generate_horizontal_gradient() -> anyhow::Result<()> {
+            let image = Image::from_fn(256, 4, 3, |x, _| vec!(x as u8, x as u8, 255 - x as u8));
+
+            assert_eq!((image.get_width(), image.get_height(), image.get_channels_per_pixel()), (256, 4, 3));
+            assert_eq!(image.get_pixel(0, 0), &vec!(0, 0, 255));
+            assert_eq!(image.get_pixel(100, 3), &vec!(100, 100, 155));
+            assert_eq!(image.get_pixel(255, 2), &vec!(255, 255, 0));
+            Ok(())
+        }
+
+        #[test]
+        fn generate_pixels_in_row_major_order() -> anyhow::Result<()> {
+            let mut next_value = 0;
+            let image = Image::from_fn(3, 2, 1, |_, _| {
+                next_value += 1;
+                vec!(next_value)
+            });
+
+            assert_eq!(image, Image::from_luma(&[1, 2, 3, 4, 5, 6], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        #[should_panic]
+        fn panic_for_pixels_of_wrong_channel_count() {
+            Image::from_fn(2, 2, 3, |x, y| vec!(x as u8, y as u8));
+        }
+
+        #[test]
+        #[should_panic]
+        fn panic_for_zero_width() {
+            Image::from_fn(0, 3, 1, |_, _| vec!(0));
+        }
+
+        #[test]
+        #[should_panic]
+        fn panic_for_zero_height() {
+            Image::from_fn(3, 0, 2, |_, _| vec!(0, 0));
+        }
+    }
+
+    mod from_luma {
+        use crate::Image;
+
+        #[test]
+        fn create_single_channel_image() -> anyhow::Result<()> {
+            let image = Image::from_luma(&[10, 20, 30, 40, 50, 60], 3)?;
+
+            assert_eq!(image.get_channels_per_pixel(), 1);
+            assert_eq!(image.get_width(), 3);
+            assert_eq!(image.get_height(), 2);
+            assert_eq!(*image.get_pixel(1, 1), vec!(50));
+            assert_eq!(image, Image::from(&[10, 20, 30, 40, 50, 60], 3, 1)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_empty_input() {
+            assert!(Image::from_luma(&[], 3).is_err());
+        }
+    }
+
+    mod from_rgb {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn return_error_for_ragged_input() {
+            let rgb_pixels = [(1, 2, 3); 5];
+            let rgba_pixels = [(1, 2, 3, 4); 5];
+
+            assert!(matches!(Image::from_rgb(&rgb_pixels, 2), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from_rgba(&rgba_pixels, 2), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from_rgb(&rgb_pixels, 0), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(Image::from_rgb(&[], 2), Err(ImgcmpError::InvalidDimensions(_))));
+        }
+
+        #[test]
+        fn create_image_of_complete_rows() -> anyhow::Result<()> {
+            let image = Image::from_rgba(&[(1, 2, 3, 4); 6], 2)?;
+
+            assert_eq!((image.get_width(), image.get_height()), (2, 3));
+            Ok(())
+        }
+    }
+
+    mod validate {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn pass_for_constructed_images() -> anyhow::Result<()> {
+            let image = Image::from(&[1, 2, 3, 4, 5, 6], 2, 3)?;
+
+            assert_eq!(image.validate(), Ok(()));
+            assert_eq!(image.map(1, |pixel| vec!(pixel[0]))?.validate(), Ok(()));
+            Ok(())
+        }
+
+        #[test]
+        fn fail_when_pixels_have_different_number_of_channels() -> anyhow::Result<()> {
+            let mut image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            image.apply(|pixel| pixel.push(255));
+
+            assert_eq!(image.validate(), Err(ImgcmpError::ChannelCountMismatch { expected : 3, actual : 4 }));
+            Ok(())
+        }
+
+        #[test]
+        fn reject_mapping_to_different_number_of_channels() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+
+            let result = image.map(1, |pixel| pixel.clone());
+
+            assert_eq!(result, Err(ImgcmpError::ChannelCountMismatch { expected : 1, actual : 3 }));
+            Ok(())
+        }
+    }
+
+    mod crop {
+        use crate::{Image, ImageSource, ImgcmpError};
+
+        #[test]
+        fn return_pixels_inside_rectangle() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2, 3, 4,
+                5, 6, 7, 8,
+                9, 10, 11, 12], 4)?;
+
+            let cropped_image = source_image.crop(1, 1, 2, 2)?;
+
+            assert_eq!(cropped_image, Image::from_luma(&[6, 7, 10, 11], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn view_pixels_inside_rectangle_without_copying_them() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2, 3, 4,
+                5, 6, 7, 8,
+                9, 10, 11, 12], 4)?;
+
+            let view = source_image.view(1, 1, 2, 2)?;
+
+            assert_eq!((view.get_width(), view.get_height(), view.get_channels_per_pixel()), (2, 2, 1));
+            assert_eq!(view.get_pixel(1, 0), &[7]);
+            assert_eq!(view.to_image()?, source_image.crop(1, 1, 2, 2)?);
+            assert!(matches!(source_image.view(3, 0, 2, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_when_rectangle_is_outside_image() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[1, 2, 3, 4], 2)?;
+
+            assert!(matches!(source_image.crop(1, 0, 2, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(source_image.crop(0, 1, 1, 2), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(source_image.crop(0, 0, 0, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(source_image.crop(u32::MAX, 0, 2, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(source_image.view(0, u32::MAX, 1, 2), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod apply_row {
+        use crate::Image;
+
+        #[test]
+        fn pass_each_row_once() -> anyhow::Result<()> {
+            let mut image = Image::from_luma(&[
+                1, 2, 3,
+                4, 5, 6], 3)?;
+
+            let mut row_lengths = Vec::new();
+            image.apply_row(|row| {
+                row_lengths.push(row.len());
+                row.reverse();
+            });
+
+            assert_eq!(row_lengths, vec!(3, 3));
+            assert_eq!(image, Image::from_luma(&[
+                3, 2, 1,
+                6, 5, 4], 3)?);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    mod map_parallel {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn return_same_image_as_serial_map() -> anyhow::Result<()> {
+            let pixels : Vec<(u8, u8, u8)> = (0..64 * 48).map(|i| ((i % 256) as u8, (i / 64) as u8, (i * 7 % 256) as u8)).collect();
+            let image = Image::from_rgb(&pixels, 64)?;
+            let to_luma = |pixel : &Vec<u8>| vec!(pixel[0] / 3 + pixel[1] / 3 + pixel[2] / 3);
+
+            assert_eq!(image.map_parallel(1, to_luma)?, image.map(1, to_luma)?);
+            assert_eq!(image.map_parallel(1, |pixel| pixel.clone()), Err(ImgcmpError::ChannelCountMismatch { expected : 1, actual : 3 }));
+            Ok(())
+        }
+
+        #[test]
+        fn apply_same_changes_as_serial_apply() -> anyhow::Result<()> {
+            let pixels : Vec<u8> = (0..64 * 48).map(|i| (i % 256) as u8).collect();
+            let mut serial_image = Image::from_luma(&pixels, 64)?;
+            let mut parallel_image = serial_image.clone();
+            let invert = |pixel : &mut Vec<u8>| pixel[0] = u8::MAX - pixel[0];
+
+            serial_image.apply(invert);
+            parallel_image.apply_parallel(invert);
+
+            assert_eq!(parallel_image, serial_image);
+            Ok(())
+        }
+    }
+
+    mod pad {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn surround_image_with_borders_of_fill_pixel() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2,
+                3, 4], 2)?;
+
+            let padded_image = source_image.pad(1, 1, 1, 1, &[0])?;
+
+            assert_eq!(padded_image, Image::from_luma(&[
+                0, 0, 0, 0,
+                0, 1, 2, 0,
+                0, 3, 4, 0,
+                0, 0, 0, 0], 4)?);
+            Ok(())
+        }
+
+        #[test]
+        fn borders_can_have_different_widths() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(10, 20, 30)], 1)?;
+
+            let padded_image = source_image.pad(0, 1, 2, 0, &[255, 0, 0])?;
+
+            assert_eq!(padded_image, Image::from_rgb(&[
+                (255, 0, 0), (255, 0, 0), (10, 20, 30),
+                (255, 0, 0), (255, 0, 0), (255, 0, 0)], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_when_fill_has_different_channels() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[1, 2, 3, 4], 2)?;
+
+            assert_eq!(source_image.pad(1, 1, 1, 1, &[0, 0, 0]), Err(ImgcmpError::ChannelCountMismatch { expected : 1, actual : 3 }));
+            Ok(())
+        }
+    }
+
+    mod concatenate {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn place_images_side_by_side() -> anyhow::Result<()> {
+            let left_image = Image::from_luma(&[
+                1, 2,
+                3, 4], 2)?;
+            let right_image = Image::from_luma(&[
+                5, 6,
+                7, 8], 2)?;
+
+            let concatenated_image = left_image.concatenate_horizontal(&right_image)?;
+
+            assert_eq!((concatenated_image.get_width(), concatenated_image.get_height()), (4, 2));
+            assert_eq!(concatenated_image, Image::from_luma(&[
+                1, 2, 5, 6,
+                3, 4, 7, 8], 4)?);
+            Ok(())
+        }
+
+        #[test]
+        fn place_images_one_below_the_other() -> anyhow::Result<()> {
+            let top_image = Image::from_rgb(&[(1, 1, 1), (2, 2, 2)], 2)?;
+            let bottom_image = Image::from_rgb(&[(3, 3, 3), (4, 4, 4), (5, 5, 5), (6, 6, 6)], 2)?;
+
+            assert_eq!(top_image.concatenate_vertical(&bottom_image)?, Image::from_rgb(&[
+                (1, 1, 1), (2, 2, 2),
+                (3, 3, 3), (4, 4, 4),
+                (5, 5, 5), (6, 6, 6)], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_incompatible_images() -> anyhow::Result<()> {
+            let luma_image = Image::from_luma(&[1, 2, 3, 4], 2)?;
+            let wide_image = Image::from_luma(&[1, 2, 3], 3)?;
+            let rgb_image = Image::from_rgb(&[(1, 1, 1), (2, 2, 2), (3, 3, 3), (4, 4, 4)], 2)?;
+
+            assert!(matches!(luma_image.concatenate_horizontal(&wide_image), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(luma_image.concatenate_vertical(&wide_image), Err(ImgcmpError::InvalidDimensions(_))));
+            assert_eq!(luma_image.concatenate_horizontal(&rgb_image), Err(ImgcmpError::ChannelCountMismatch { expected : 1, actual : 3 }));
+            assert_eq!(luma_image.concatenate_vertical(&rgb_image), Err(ImgcmpError::ChannelCountMismatch { expected : 1, actual : 3 }));
+            Ok(())
+        }
+    }
+
+    mod trim_border {
+        use crate::Image;
+
+        #[test]
+        fn remove_uniform_rows_and_columns_around_content() -> anyhow::Result<()> {
+            let content = Image::from_luma(&[
+                10, 200, 30,
+                40, 50, 60], 3)?;
+            let bordered_image = content.pad(2, 1, 3, 2, &[0])?;
+
+            assert_eq!(bordered_image.trim_border(0), content);
+            assert_eq!(content.trim_border(0), content);
+            Ok(())
+        }
+
+        #[test]
+        fn remove_borders_within_tolerance() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                250, 255, 252, 255,
+                253, 10, 20, 251,
+                255, 30, 40, 255,
+                255, 254, 255, 250], 4)?;
+
+            assert_eq!(source_image.trim_border(5), Image::from_luma(&[10, 20, 30, 40], 2)?);
+            assert_eq!(source_image.trim_border(2), source_image);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_uniform_image() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(1, 2, 3); 6], 3)?;
+
+            assert_eq!(source_image.trim_border(0), source_image);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "image")]
+    mod to_dynamic_image {
+        use crate::{Image, ImgcmpError};
+        use ::image::GenericImageView;
+
+        #[test]
+        fn convert_decoded_image_back_to_dynamic_image() -> anyhow::Result<()> {
+            let decoded_image = ::image::open("../assets/cat.jpg")?;
+            let image = Image::from(decoded_image.as_bytes(), decoded_image.width(), decoded_image.color().channel_count())?;
+
+            let dynamic_image = image.to_dynamic_image()?;
+
+            assert_eq!(dynamic_image.dimensions(), decoded_image.dimensions());
+            assert_eq!(dynamic_image.color(), decoded_image.color());
+            assert_eq!(dynamic_image.get_pixel(100, 50), decoded_image.get_pixel(100, 50));
+            assert_eq!(dynamic_image.as_bytes(), decoded_image.as_bytes());
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_unsupported_channel_count() -> anyhow::Result<()> {
+            let image = Image::from(&[0; 10], 2, 5)?;
+
+            assert!(matches!(image.to_dynamic_image(), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "image")]
+    mod from_encoded {
+        use crate::{Image, ImgcmpError};
+        use ::image::GenericImageView;
+
+        #[test]
+        fn decode_image_from_file_contents() -> anyhow::Result<()> {
+            let bytes = std::fs::read("../assets/cat.jpg")?;
+            let decoded_image = ::image::open("../assets/cat.jpg")?;
+
+            let image = Image::from_encoded(&bytes)?;
+
+            assert_eq!((image.get_width(), image.get_height()), decoded_image.dimensions());
+            assert_eq!(image.get_channels_per_pixel(), 3);
+            assert_eq!(image.to_bytes(), decoded_image.as_bytes());
+            Ok(())
+        }
+
+        #[test]
+        fn convert_16_bit_images_to_8_bit() -> anyhow::Result<()> {
+            let luma_buffer = ::image::ImageBuffer::from_raw(3, 1, vec!(0_u16, 0x12ff, 0xffff)).unwrap();
+            let rgb_buffer = ::image::ImageBuffer::from_raw(1, 1, vec!(0x0100_u16, 0x80ff, 0xfe00)).unwrap();
+
+            let luma_image = Image::from_dynamic_image(&::image::DynamicImage::ImageLuma16(luma_buffer))?;
+            let rgb_image = Image::from_dynamic_image(&::image::DynamicImage::ImageRgb16(rgb_buffer))?;
+
+            assert_eq!(luma_image, Image::from_luma(&[0, 0x12, 0xff], 3)?);
+            assert_eq!(rgb_image, Image::from_rgb(&[(1, 0x80, 0xfe)], 1)?);
+            Ok(())
+        }
+
+        #[test]
+        fn convert_other_images_to_rgba() -> anyhow::Result<()> {
+            let dynamic_image = ::image::DynamicImage::new_bgr8(3, 2);
+
+            let image = Image::from_dynamic_image(&dynamic_image)?;
+
+            assert_eq!(image, Image::from_rgba(&[(0, 0, 0, 255); 6], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn apply_exif_orientation() -> anyhow::Result<()> {
+            let rotated_image = Image::load("../assets/cat_exif_rotated.jpg")?;
+            let stored_image = ::image::open("../assets/cat_exif_rotated.jpg")?;
+
+            assert_eq!((rotated_image.get_width(), rotated_image.get_height()), (stored_image.height(), stored_image.width()));
+            assert_eq!(rotated_image, Image::from_dynamic_image(&stored_image)?.orient(6));
+            Ok(())
+        }
+
+        #[test]
+        fn load_image_file() -> anyhow::Result<()> {
+            let bytes = std::fs::read("../assets/cat.jpg")?;
+
+            assert_eq!(Image::load("../assets/cat.jpg")?, Image::from_encoded(&bytes)?);
+            Ok(())
+        }
+
+        #[test]
+        fn mention_path_in_load_errors() {
+            let missing_error = Image::load("../assets/missing.jpg").unwrap_err();
+            let decode_error = Image::load("../Cargo.toml").unwrap_err();
+
+            assert!(matches!(&missing_error, ImgcmpError::Io(reason) if reason.contains("missing.jpg")), "{}", missing_error);
+            assert!(matches!(&decode_error, ImgcmpError::Decode(reason) if reason.contains("Cargo.toml")), "{}", decode_error);
+        }
+
+        #[test]
+        fn return_error_for_invalid_bytes() {
+            assert!(matches!(Image::from_encoded(&[1, 2, 3, 4]), Err(ImgcmpError::Decode(_))));
+        }
+    }
+
+    mod pixels_len {
+        use crate::Image;
+
+        #[test]
+        fn count_pixels_of_image() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(1, 1, 1), (2, 2, 2), (3, 3, 3), (4, 4, 4), (5, 5, 5), (6, 6, 6)], 3)?;
+
+            assert_eq!(image.pixels_len(), 6);
+            assert!(!image.is_empty());
+            assert_eq!(Image::from_fn(5, 7, 1, |_, _| vec!(0)).pixels_len(), 35);
+            Ok(())
+        }
+
+    }
+
+    mod get_pixel {
+        use crate::Image;
+        use crate::image::row_major_index;
+
+        #[test]
+        fn do_not_wrap_index_of_pixels_past_u32_range() {
+            let (width, height) = (100_000, 50_000);
+
+            let last_index = row_major_index(width, width - 1, height - 1);
+
+            assert_eq!(last_index, 4_999_999_999);
+            assert!(last_index > u32::MAX as usize);
+        }
+
+        #[test]
+        #[should_panic]
+        fn panic_for_pixel_outside_of_image() {
+            let image = Image::from_luma(&[1, 2, 3, 4, 5, 6], 3).unwrap();
+
+            // Would be pixel (0, 1) if the coordinates were not checked
+            image.get_pixel(3, 0);
+        }
+    }
+
+    mod sample_bilinear {
+        use crate::Image;
+
+        #[test]
+        fn blend_pixels_around_midpoint() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[
+                (0, 100, 10), (100, 100, 20),
+                (0, 200, 30), (100, 200, 40)], 2)?;
+
+            assert_eq!(source_image.sample_bilinear(0.5, 0.5), vec!(50, 150, 25));
+            assert_eq!(source_image.sample_bilinear(0.25, 0.0), vec!(25, 100, 13));
+            assert_eq!(source_image.sample_bilinear(1.0, 1.0), vec!(100, 200, 40));
+            Ok(())
+        }
+
+        #[test]
+        fn clamp_coordinates_outside_of_image() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                10, 20,
+                30, 40], 2)?;
+
+            assert_eq!(source_image.sample_bilinear(-3.0, -1.0), vec!(10));
+            assert_eq!(source_image.sample_bilinear(5.0, 0.5), vec!(30));
+            assert_eq!(source_image.sample_bilinear(0.5, 7.0), vec!(35));
+            Ok(())
+        }
+    }
+
+    mod crop_to_aspect {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn crop_center_of_wide_image_to_square() -> anyhow::Result<()> {
+            let pixels : Vec<u8> = (0..16 * 9).map(|i| (i % 16) as u8).collect();
+            let image = Image::from_luma(&pixels, 16)?;
+
+            let cropped_image = image.crop_to_aspect(1, 1)?;
+
+            assert_eq!((cropped_image.get_width(), cropped_image.get_height()), (9, 9));
+            // Pixels hold their column, so the first column is the horizontal offset
+            assert_eq!(*cropped_image.get_pixel(0, 0), vec!(3));
+            assert_eq!(cropped_image, image.crop(3, 0, 9, 9)?);
+            Ok(())
+        }
+
+        #[test]
+        fn crop_center_of_tall_image_to_wide_ratio() -> anyhow::Result<()> {
+            let image = Image::from_luma(&[0; 10 * 20], 10)?;
+
+            let cropped_image = image.crop_to_aspect(2, 1)?;
+
+            assert_eq!((cropped_image.get_width(), cropped_image.get_height()), (10, 5));
+            assert!(matches!(image.crop_to_aspect(0, 1), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod sub_sample {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn average_blocks_of_factor() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[
+                (0, 10, 20), (2, 10, 20), (100, 0, 0), (100, 0, 255),
+                (4, 10, 20), (6, 11, 20), (100, 0, 0), (100, 0, 255)], 4)?;
+
+            assert_eq!(source_image.sub_sample(2)?, Image::from_rgb(&[(3, 10, 20), (100, 0, 127)], 2)?);
+            assert_eq!(source_image.sub_sample(2)?, source_image.scale(2, 1)?);
+            assert!(matches!(source_image.sub_sample(3), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod scale {
+        use crate::Image;
+        use crate::image_processing::scale_image;
+
+        #[test]
+        fn return_same_image_as_scale_image() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2, 3, 4,
+                5, 6, 7, 8,
+                9, 10, 11, 12,
+                13, 14, 15, 16], 4)?;
+
+            assert_eq!(source_image.scale(2, 2)?, scale_image(&source_image, 2, 2)?);
+            assert_eq!(source_image.scale(6, 3)?, scale_image(&source_image, 6, 3)?);
+            Ok(())
+        }
+    }
+
+    mod orient {
+        use crate::Image;
+
+        #[test]
+        fn rotate_and_flip_to_display_orientation() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2, 3,
+                4, 5, 6], 3)?;
+
+            assert_eq!(source_image.orient(1), source_image);
+            assert_eq!(source_image.orient(2), Image::from_luma(&[3, 2, 1, 6, 5, 4], 3)?);
+            assert_eq!(source_image.orient(3), Image::from_luma(&[6, 5, 4, 3, 2, 1], 3)?);
+            assert_eq!(source_image.orient(4), Image::from_luma(&[4, 5, 6, 1, 2, 3], 3)?);
+            assert_eq!(source_image.orient(5), Image::from_luma(&[1, 4, 2, 5, 3, 6], 2)?);
+            assert_eq!(source_image.orient(6), Image::from_luma(&[4, 1, 5, 2, 6, 3], 2)?);
+            assert_eq!(source_image.orient(7), Image::from_luma(&[6, 3, 5, 2, 4, 1], 2)?);
+            assert_eq!(source_image.orient(8), Image::from_luma(&[3, 6, 2, 5, 1, 4], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_image_of_unknown_orientation() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(1, 2, 3), (4, 5, 6)], 1)?;
+
+            assert_eq!(source_image.orient(0), source_image);
+            assert_eq!(source_image.orient(9), source_image);
+            Ok(())
+        }
+    }
+
+    mod grayscale {
+        use crate::Image;
+
+        #[test]
+        fn return_average_of_all_channels() -> anyhow::Result<()> {
+            let color1 = (100, 200, 50);
+            let color2 = (20, 150, 80);
+            let color3 = (255, 10, 0);
+            let source_image = Image::from_rgb(&[
+                color1, color2, color3,
+                color1, color3, color2], 3)?;
+
+            let grayscale_image = source_image.grayscale();
+
+            assert_eq!(grayscale_image, Image::from_luma(&[
+                116, 83, 88,
+                116, 88, 83], 3)?);
+            assert_eq!(source_image.into_grayscale(), grayscale_image);
+            Ok(())
+        }
+
+        #[test]
+        fn return_original_image_when_already_grayscale() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[10, 20, 30, 40, 50, 60], 3)?;
+
+            assert_eq!(source_image.grayscale(), source_image);
+            Ok(())
+        }
+    }
+
+    mod thumbnail {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn preserve_aspect_ratio_and_channels() -> anyhow::Result<()> {
+            let wide_image = Image::from(&vec!(100; 100 * 50 * 3), 100, 3)?;
+            let tall_image = Image::from(&vec!(100; 50 * 100 * 4), 50, 4)?;
+
+            let wide_thumbnail = wide_image.thumbnail(20)?;
+            let tall_thumbnail = tall_image.thumbnail(20)?;
+
+            assert_eq!((wide_thumbnail.get_width(), wide_thumbnail.get_height()), (20, 10));
+            assert_eq!(wide_thumbnail.get_channels_per_pixel(), 3);
+            assert_eq!((tall_thumbnail.get_width(), tall_thumbnail.get_height()), (10, 20));
+            assert_eq!(tall_thumbnail.get_channels_per_pixel(), 4);
+            assert_eq!(tall_image.fit_dimensions(20), (10, 20));
+            Ok(())
+        }
+
+        #[test]
+        fn scale_by_non_integer_factor() -> anyhow::Result<()> {
+            let image = Image::from_luma(&[1; 22 * 22], 22)?;
+
+            assert_eq!(image.thumbnail(13)?, Image::from_luma(&[1; 13 * 13], 13)?);
+            assert_eq!(image.scale(13, 13)?, Image::from_luma(&[1; 13 * 13], 13)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_zero_edge() -> anyhow::Result<()> {
+            let image = Image::from(&vec!(100; 100 * 50 * 3), 100, 3)?;
+
+            assert_eq!(image.thumbnail(0), Err(ImgcmpError::ScaleError { width : 0, height : 1 }));
+            Ok(())
+        }
+    }
+
+    mod channel {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn extract_green_channel_of_rgb_image() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80), (0, 7, 255)], 3)?;
+
+            let green_image = source_image.channel(1)?;
+
+            assert_eq!(green_image, Image::from_luma(&[200, 150, 7], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_missing_channel() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50)], 1)?;
+
+            assert_eq!(source_image.channel(3), Err(ImgcmpError::InvalidChannel { channel : 3, channels_per_pixel : 3 }));
+            Ok(())
+        }
+    }
+
+    mod equalize {
+        use crate::Image;
+
+        #[test]
+        fn spread_values_over_full_range() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[100, 100, 101, 102, 102, 103], 3)?;
+
+            let equalized_image = source_image.equalize();
+
+            assert_eq!(equalized_image, Image::from_luma(&[0, 0, 64, 191, 191, 255], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_uniform_channels_unchanged() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(7, 10, 0), (7, 20, 0)], 2)?;
+
+            let equalized_image = source_image.equalize();
+
+            assert_eq!(equalized_image, Image::from_rgb(&[(7, 0, 0), (7, 255, 0)], 2)?);
+            Ok(())
+        }
+    }
+
+    mod channel_means {
+        use crate::Image;
+
+        #[test]
+        fn return_average_of_each_channel() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80), (255, 10, 0), (5, 0, 10)], 2)?;
+
+            assert_eq!(image.channel_means(), vec!(95.0, 90.0, 35.0));
+            Ok(())
+        }
+
+        #[test]
+        fn return_average_variance_of_channels() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(0, 10, 50), (10, 10, 50), (0, 30, 50), (10, 30, 50)], 2)?;
+
+            assert_eq!(image.variance(), (25.0 + 100.0 + 0.0) / 3.0);
+            assert_eq!(Image::from_rgb(&[(255, 0, 0); 4], 2)?.variance(), 0.0);
+            Ok(())
+        }
+
+        #[test]
+        fn return_average_of_all_channels_as_brightness() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80), (255, 10, 0), (5, 0, 10)], 2)?;
+
+            assert_eq!(image.mean_brightness(), 220.0 / 3.0);
+            Ok(())
+        }
+    }
+
+    mod adjust_brightness {
+        use crate::Image;
+
+        #[test]
+        fn saturate_instead_of_wrapping_around() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(200, 50, 0), (255, 100, 155)], 2)?;
+
+            assert_eq!(image.adjust_brightness(100), Image::from_rgb(&[(255, 150, 100), (255, 200, 255)], 2)?);
+            assert_eq!(image.adjust_brightness(-100), Image::from_rgb(&[(100, 0, 0), (155, 0, 55)], 2)?);
+            assert_eq!(image.adjust_brightness(i16::MAX), Image::from_rgb(&[(255, 255, 255), (255, 255, 255)], 2)?);
+            assert_eq!(image.adjust_brightness(0), image);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_alpha_unchanged() -> anyhow::Result<()> {
+            let image = Image::from_rgba(&[(200, 50, 0, 128), (255, 100, 155, 255)], 2)?;
+
+            assert_eq!(image.adjust_brightness(-60), Image::from_rgba(&[(140, 0, 0, 128), (195, 40, 95, 255)], 2)?);
+            Ok(())
+        }
+    }
+
+    mod invert {
+        use crate::Image;
+
+        #[test]
+        fn invert_color_channels() -> anyhow::Result<()> {
+            let image = Image::from_rgb(&[(0, 100, 255), (10, 128, 200)], 2)?;
+
+            assert_eq!(image.invert(), Image::from_rgb(&[(255, 155, 0), (245, 127, 55)], 2)?);
+            assert_eq!(image.invert().invert(), image);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_alpha_unchanged() -> anyhow::Result<()> {
+            let image = Image::from(&[0, 128, 200, 255], 2, 2)?;
+
+            assert_eq!(image.invert(), Image::from(&[255, 128, 55, 255], 2, 2)?);
+            Ok(())
+        }
+    }
+
+    mod normalize_brightness {
+        use crate::Image;
+
+        #[test]
+        fn shift_mean_brightness_to_target() -> anyhow::Result<()> {
+            let image = Image::from_luma(&[10, 20, 30, 40], 2)?;
+
+            assert_eq!(image.normalize_brightness(125), Image::from_luma(&[110, 120, 130, 140], 2)?);
+            assert_eq!(image.normalize_brightness(250), Image::from_luma(&[235, 245, 255, 255], 2)?);
+            Ok(())
+        }
+    }
+
+    mod blend {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn composite_half_transparent_red_over_white_into_pink() -> anyhow::Result<()> {
+            let white_image = Image::from_rgba(&[(255, 255, 255, 255); 2], 2)?;
+            let red_image = Image::from_rgba(&[(255, 0, 0, 128), (255, 0, 0, 0)], 2)?;
+
+            assert_eq!(white_image.blend(&red_image)?, Image::from_rgba(&[(255, 127, 127, 255), (255, 255, 255, 255)], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn composite_over_transparent_background() -> anyhow::Result<()> {
+            let background_image = Image::from_rgba(&[(0, 0, 255, 0), (0, 0, 255, 128), (0, 0, 0, 0)], 3)?;
+            let foreground_image = Image::from_rgba(&[(255, 0, 0, 128), (255, 0, 0, 128), (0, 0, 0, 0)], 3)?;
+
+            assert_eq!(background_image.blend(&foreground_image)?,
+                       Image::from_rgba(&[(255, 0, 0, 128), (170, 0, 85, 192), (0, 0, 0, 0)], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_images_other_than_rgba_of_same_dimensions() -> anyhow::Result<()> {
+            let rgba_image = Image::from_rgba(&[(255, 0, 0, 128), (255, 0, 0, 0)], 2)?;
+            let rgb_image = Image::from_rgb(&[(255, 0, 0), (255, 0, 0)], 2)?;
+            let tall_image = Image::from_rgba(&[(255, 0, 0, 128), (255, 0, 0, 0)], 1)?;
+
+            assert_eq!(rgb_image.blend(&rgb_image), Err(ImgcmpError::ChannelCountMismatch { expected : 4, actual : 3 }));
+            assert_eq!(rgba_image.blend(&rgb_image), Err(ImgcmpError::ChannelCountMismatch { expected : 4, actual : 3 }));
+            assert!(matches!(rgba_image.blend(&tall_image), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod difference {
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn return_absolute_difference_of_each_channel() -> anyhow::Result<()> {
+            let image1 = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            let image2 = Image::from_rgb(&[(110, 200, 40), (20, 155, 80)], 2)?;
+
+            let difference = image1.difference(&image2)?;
+
+            assert_eq!(difference, Image::from_rgb(&[(10, 0, 10), (0, 5, 0)], 2)?);
+            assert_eq!(difference.to_bytes(), vec!(10, 0, 10, 0, 5, 0));
+            assert_eq!(image2.difference(&image1)?, difference);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_images_of_different_shape() -> anyhow::Result<()> {
+            let rgb_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            let rgba_image = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 80, 255)], 2)?;
+            let tall_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 1)?;
+
+            assert_eq!(rgb_image.difference(&rgba_image), Err(ImgcmpError::ChannelCountMismatch { expected : 3, actual : 4 }));
+            assert!(matches!(rgb_image.difference(&tall_image), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod visually_equal {
+        use crate::Image;
+
+        #[test]
+        fn rgb_image_is_equal_with_opaque_rgba_image() -> anyhow::Result<()> {
+            let rgb_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            let rgba_image = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 80, 255)], 2)?;
+
+            assert!(rgb_image.visually_equal(&rgba_image));
+            assert!(rgba_image.visually_equal(&rgb_image));
+            Ok(())
+        }
+
+        #[test]
+        fn rgb_image_is_not_equal_with_transparent_rgba_image() -> anyhow::Result<()> {
+            let rgb_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            let rgba_image = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 80, 128)], 2)?;
+
+            assert!(!rgb_image.visually_equal(&rgba_image));
+            assert!(!rgba_image.visually_equal(&rgb_image));
+            Ok(())
+        }
+
+        #[test]
+        fn rgba_images_with_different_alpha_are_not_equal() -> anyhow::Result<()> {
+            let rgba_image1 = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 80, 0)], 2)?;
+            let rgba_image2 = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 80, 10)], 2)?;
+
+            assert!(!rgba_image1.visually_equal(&rgba_image2));
+            Ok(())
+        }
+
+        #[test]
+        fn images_with_different_colors_are_not_equal() -> anyhow::Result<()> {
+            let rgb_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80)], 2)?;
+            let rgba_image = Image::from_rgba(&[(100, 200, 50, 255), (20, 150, 81, 255)], 2)?;
+
+            assert!(!rgb_image.visually_equal(&rgba_image));
+            Ok(())
+        }
+    }
 }
@@ -1,27 +1,110 @@
 use crate::image::*;
-use anyhow::{anyhow};
+use crate::error::{ImgcmpError, Result};
+
+/// Interpolation used along the dimensions that are increased when scaling an image
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum Interpolation {
+    /// Duplicates the nearest source pixel
+    Nearest,
+    /// Blends the two nearest source pixels, weighted by their distance
+    Bilinear
+}
+
+/// How source pixels are sampled along the dimensions that are decreased when scaling an image
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum ScaleMode {
+    /// Averages all source pixels covered by the new pixel
+    Area,
+    /// Reads the single source pixel nearest to the center of the new pixel, which is faster and keeps hard edges
+    Point
+}
+
+/// How averages of channels are rounded to integer channel values. It applies to the area averages of `ScaleMode::Area`,
+/// including masked and alpha weighted ones, and to `GrayscaleMode::Average`. Lumas, bilinear interpolation, blurring
+/// and compositing always round to the nearest integer, while `ScaleMode::Point` and `Interpolation::Nearest` copy pixels.
+/// Changing it changes the hashes of most images, `Floor` is the historical behavior, while other pHash implementations
+/// usually `Round`.
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum RoundingMode {
+    /// Rounds down
+    Floor,
+    /// Rounds to the nearest integer, halves up
+    Round,
+    /// Rounds up
+    Ceil
+}
+
+impl RoundingMode {
+    /// Divides `sum` by `count`, both non negative, rounding the quotient. Computed on integers,
+    /// so that sums of huge windows are not truncated by the precision of floats.
+    fn divide(self, sum : u64, count : u64) -> u64 {
+        match self {
+            RoundingMode::Floor => sum / count,
+            RoundingMode::Round => (sum + count / 2) / count,
+            RoundingMode::Ceil => sum.div_ceil(count)
+        }
+    }
+}
 
 /// Scales an image using for each pixel in the new image, the average of its
-/// nearest pixels in the original image
-pub fn scale_image(image : &Image, new_width : u32, new_height : u32) -> anyhow::Result<Image> {
+/// nearest pixels in the original image. Increased dimensions are interpolated bilinearly.
+pub fn scale_image<S : ImageSource + ?Sized>(image : &S, new_width : u32, new_height : u32) -> Result<Image> {
+    scale_image_with(image, new_width, new_height, Interpolation::Bilinear, ScaleMode::Area, RoundingMode::Floor)
+}
+
+/// Scales an image so that its longer edge is `max_edge`, preserving its aspect ratio, see `fit_dimensions`.
+pub fn scale_image_fit<S : ImageSource + ?Sized>(image : &S, max_edge : u32) -> Result<Image> {
+    let (width, height) = fit_dimensions(image.get_width(), image.get_height(), max_edge);
+    scale_image(image, width, height)
+}
+
+/// Dimensions of an image of `width`x`height` scaled so that its longer edge is `max_edge`, preserving its aspect ratio.
+/// The shorter edge is rounded to the nearest integer, and is at least 1.
+pub fn fit_dimensions(width : u32, height : u32, max_edge : u32) -> (u32, u32) {
+    let longer_edge = width.max(height) as f32;
+    let scaled_edge = |edge : u32| ((edge as f32 * max_edge as f32 / longer_edge).round() as u32).max(1);
+    if width >= height {
+        (max_edge, scaled_edge(height))
+    } else {
+        (scaled_edge(width), max_edge)
+    }
+}
+
+/// Scales an image sampling decreased dimensions with `downscale_mode` and interpolating increased
+/// dimensions with `upscale_interpolation`. Area averages are rounded with `rounding`.
+pub fn scale_image_with<S : ImageSource + ?Sized>(image : &S, new_width : u32, new_height : u32, upscale_interpolation : Interpolation,
+                                                  downscale_mode : ScaleMode, rounding : RoundingMode) -> Result<Image> {
     if new_width == 0 || new_height == 0 {
-        return Err(anyhow!("Passed dimensions should not be zero"));
+        return Err(ImgcmpError::ScaleError { width : new_width, height : new_height });
     }
 
     if new_width == image.get_width() && new_height == image.get_height() {
-        return Ok(image.clone());
+        return image.to_image();
+    }
+
+    // Integer factors average disjoint blocks, which is faster and immune to the rounding of the float windows
+    let is_integer_factor = |length : u32, new_length : u32| new_length <= length && length.is_multiple_of(new_length);
+    if downscale_mode == ScaleMode::Area &&
+        is_integer_factor(image.get_width(), new_width) && is_integer_factor(image.get_height(), new_height) {
+        return sub_sample(image, image.get_width() / new_width, image.get_height() / new_height, rounding);
     }
 
     let scale_x = new_width as f32 / image.get_width() as f32;
     let scale_y = new_height as f32 / image.get_height() as f32;
+    let is_interpolated = upscale_interpolation == Interpolation::Bilinear && (scale_x > 1.0 || scale_y > 1.0);
 
     let mut scaled_data = Vec::new();
     for new_y in 0..new_height {
         for new_x in 0..new_width {
-            let pixel = sample_pixels(image, new_x, new_y, scale_x, scale_y);
-            for channel in pixel {
-                scaled_data.push(channel as u8);
-            }
+            let pixel = if is_interpolated || downscale_mode == ScaleMode::Point {
+                interpolate_pixels(image, (new_x, new_y), (scale_x, scale_y), upscale_interpolation, downscale_mode)
+            } else {
+                sample_pixels(image, new_x, new_y, scale_x, scale_y, rounding)
+            };
+            scaled_data.extend(pixel.into_iter().map(saturate_channel));
         }
     }
 
@@ -29,62 +112,368 @@ pub fn scale_image(image : &Image, new_width : u32, new_height : u32) -> anyhow:
     Ok(scaled_image)
 }
 
-fn sample_pixels(image: &Image, new_x: u32, new_y: u32, scale_x: f32, scale_y: f32) -> Vec<u32> {
-    let left = (new_x as f32 / scale_x).floor() as u32;
-    let right = ((new_x + 1) as f32 / scale_x).ceil() as u32;
-    let top = (new_y as f32 / scale_y).floor() as u32;
-    let bottom = ((new_y + 1) as f32 / scale_y).ceil() as u32;
+/// Scales an image down by integer factors, averaging each disjoint block of `factor_x`x`factor_y` pixels
+/// into a new pixel, with the averages rounded with `rounding`. The factors should divide the dimensions of the image.
+pub fn sub_sample<S : ImageSource + ?Sized>(image : &S, factor_x : u32, factor_y : u32, rounding : RoundingMode) -> Result<Image> {
+    if factor_x == 0 || factor_y == 0 || !image.get_width().is_multiple_of(factor_x) || !image.get_height().is_multiple_of(factor_y) {
+        return Err(ImgcmpError::InvalidDimensions(format!("{}x{} image cannot be divided into blocks of {}x{} pixels",
+                                                          image.get_width(), image.get_height(), factor_x, factor_y)));
+    }
 
-    let mut original_pixels = Vec::new();
-    for x in left..right {
-        for y in top..bottom {
-            let original_pixel = image.get_pixel(x, y);
-            original_pixels.push(original_pixel);
+    let (new_width, new_height) = (image.get_width() / factor_x, image.get_height() / factor_y);
+    let block_size = factor_x as u64 * factor_y as u64;
+    let mut sums = vec!(0_u64; image.get_channels_per_pixel() as usize);
+    let mut scaled_data = Vec::with_capacity(new_width as usize * new_height as usize * sums.len());
+    for new_y in 0..new_height {
+        for new_x in 0..new_width {
+            sums.iter_mut().for_each(|sum| *sum = 0);
+            for y in new_y * factor_y..(new_y + 1) * factor_y {
+                for x in new_x * factor_x..(new_x + 1) * factor_x {
+                    for (sum, channel) in sums.iter_mut().zip(image.get_pixel(x, y).iter()) {
+                        *sum += *channel as u64;
+                    }
+                }
+            }
+            scaled_data.extend(sums.iter().map(|sum| rounding.divide(*sum, block_size) as u8));
         }
     }
 
-    average_pixels(&original_pixels)
+    Image::from(&scaled_data, new_width, image.get_channels_per_pixel())
 }
 
-fn average_pixels(pixels: &[&Vec<u8>]) -> Vec<u32> {
-    let channels_per_pixel = pixels[0].len();
+/// Scales an image like `scale_image`, excluding the pixels flagged in `mask` from the averages.
+/// The mask has one flag per pixel, in row-major order. New pixels covering only masked pixels are zero.
+pub fn scale_image_masked(image : &Image, mask : &[bool], new_width : u32, new_height : u32, rounding : RoundingMode) -> Result<Image> {
+    let channels_per_pixel = image.get_channels_per_pixel() as usize;
+    scale_image_weighted(image, Some(mask), new_width, new_height, channels_per_pixel, rounding, |_| 1)
+}
 
-    let mut average_pixel = Vec::new();
-    for _i in 0..channels_per_pixel {
-        average_pixel.push(0 as u32);
+/// Scales an image with an alpha channel, i.e. RGBA or luma with alpha, averaging its colors weighted by their alpha,
+/// Σ(α·c)/Σα, so that mostly transparent pixels influence the averages proportionally less than the opaque pixels
+/// around them. The alpha channel is dropped and new pixels covering only transparent pixels are black.
+/// Pixels flagged in `mask` are excluded from the averages, as in `scale_image_masked`.
+/// Images of other channels have no alpha, their pixels are weighted equally.
+pub fn scale_image_alpha_weighted(image : &Image, mask : Option<&[bool]>, new_width : u32, new_height : u32,
+                                  rounding : RoundingMode) -> Result<Image> {
+    let channels_per_pixel = image.get_channels_per_pixel() as usize;
+    let alpha = match channels_per_pixel {
+        2 | 4 => Some(channels_per_pixel - 1),
+        _ => None
+    };
+    scale_image_weighted(image, mask, new_width, new_height, alpha.unwrap_or(channels_per_pixel), rounding,
+                         |pixel| alpha.map_or(1, |alpha| pixel[alpha] as u64))
+}
+
+/// Scales an image averaging the first `channels_per_pixel` channels of the pixels covered by each new pixel,
+/// weighted by `weight` and excluding the pixels flagged in `mask`. New pixels of zero total weight are zero.
+fn scale_image_weighted<F>(image : &Image, mask : Option<&[bool]>, new_width : u32, new_height : u32, channels_per_pixel : usize,
+                           rounding : RoundingMode, weight : F) -> Result<Image> where F: Fn(&[u8]) -> u64 {
+    let pixel_count = image.get_width() as usize * image.get_height() as usize;
+    match mask {
+        Some(mask) if mask.len() != pixel_count =>
+            return Err(ImgcmpError::BufferLengthMismatch { expected : pixel_count, actual : mask.len() }),
+        _ => ()
+    }
+    if new_width == 0 || new_height == 0 {
+        return Err(ImgcmpError::ScaleError { width : new_width, height : new_height });
     }
 
+    let scale_x = new_width as f32 / image.get_width() as f32;
+    let scale_y = new_height as f32 / image.get_height() as f32;
+    let is_masked = |x : u32, y : u32| mask.is_some_and(|mask| mask[y as usize * image.get_width() as usize + x as usize]);
+
+    let mut sums = vec!(0_u64; channels_per_pixel);
+    let mut scaled_data = Vec::new();
+    for new_y in 0..new_height {
+        for new_x in 0..new_width {
+            let (left, right) = sample_window(new_x, scale_x, image.get_width());
+            let (top, bottom) = sample_window(new_y, scale_y, image.get_height());
+            sums.iter_mut().for_each(|sum| *sum = 0);
+            let mut total_weight = 0_u64;
+            for y in top..bottom {
+                for x in left..right {
+                    if is_masked(x, y) {
+                        continue;
+                    }
+                    let pixel = image.get_pixel(x, y);
+                    let pixel_weight = weight(pixel);
+                    total_weight += pixel_weight;
+                    for (sum, channel) in sums.iter_mut().zip(pixel.iter()) {
+                        *sum += pixel_weight * *channel as u64;
+                    }
+                }
+            }
+            if total_weight == 0 {
+                scaled_data.extend(std::iter::repeat_n(0, channels_per_pixel));
+            } else {
+                scaled_data.extend(sums.iter().map(|sum| rounding.divide(*sum, total_weight) as u8));
+            }
+        }
+    }
+
+    Image::from(&scaled_data, new_width, channels_per_pixel as u8)
+}
+
+/// Range of source positions covered by a new position along a single axis of the given length. The range is
+/// clamped to the axis, since rounding errors of the division may push its end past the last position.
+fn sample_window(new_position : u32, scale : f32, length : u32) -> (u32, u32) {
+    let end = (((new_position + 1) as f32 / scale).ceil() as u32).min(length);
+    let start = ((new_position as f32 / scale).floor() as u32).min(end - 1);
+    (start, end)
+}
+
+fn sample_pixels<S : ImageSource + ?Sized>(image: &S, new_x: u32, new_y: u32, scale_x: f32, scale_y: f32, rounding : RoundingMode) -> Vec<f32> {
+    let (left, right) = sample_window(new_x, scale_x, image.get_width());
+    let (top, bottom) = sample_window(new_y, scale_y, image.get_height());
+
+    let original_pixels = (left..right).
+        flat_map(|x| (top..bottom).map(move |y| (x, y))).
+        map(|(x, y)| image.get_pixel(x, y));
+    average_pixels(original_pixels, rounding)
+}
+
+fn average_pixels<'a, I>(pixels : I, rounding : RoundingMode) -> Vec<f32> where I: IntoIterator<Item = &'a [u8]> {
+    let mut pixels = pixels.into_iter().peekable();
+    let channels_per_pixel = pixels.peek().map_or(0, |pixel| pixel.len());
+
+    // Sums of windows of more than 16M pixels overflow u32
+    let mut average_pixel = vec!(0_u64; channels_per_pixel);
+    let mut pixel_count = 0_u64;
     for pixel in pixels {
-        for i in 0..pixel.len() {
-            average_pixel[i] += pixel[i] as u32;
+        for (sum, channel) in average_pixel.iter_mut().zip(pixel.iter()) {
+            *sum += *channel as u64;
+        }
+        pixel_count += 1;
+    }
+
+    average_pixel.iter().map(|channel| rounding.divide(*channel, pixel_count) as f32).collect()
+}
+
+fn interpolate_pixels<S : ImageSource + ?Sized>(image : &S, (new_x, new_y) : (u32, u32), (scale_x, scale_y) : (f32, f32),
+                                               upscale_interpolation : Interpolation, downscale_mode : ScaleMode) -> Vec<f32> {
+    let horizontal_weights = axis_weights(new_x, scale_x, image.get_width(), upscale_interpolation, downscale_mode);
+    let vertical_weights = axis_weights(new_y, scale_y, image.get_height(), upscale_interpolation, downscale_mode);
+
+    let mut pixel = vec!(0.0; image.get_channels_per_pixel() as usize);
+    for (x, horizontal_weight) in &horizontal_weights {
+        for (y, vertical_weight) in &vertical_weights {
+            for (channel, value) in pixel.iter_mut().zip(image.get_pixel(*x, *y).iter()) {
+                *channel += horizontal_weight * vertical_weight * *value as f32;
+            }
+        }
+    }
+    pixel.iter().map(|channel| channel.round()).collect()
+}
+
+/// Source pixels contributing to a new pixel along a single axis, along with their weights
+fn axis_weights(new_position : u32, scale : f32, length : u32, upscale_interpolation : Interpolation,
+                downscale_mode : ScaleMode) -> Vec<(u32, f32)> {
+    let is_nearest = if scale > 1.0 {
+        upscale_interpolation == Interpolation::Nearest
+    } else {
+        downscale_mode == ScaleMode::Point
+    };
+
+    if is_nearest {
+        let nearest = (((new_position as f32 + 0.5) / scale) as u32).min(length - 1);
+        vec!((nearest, 1.0))
+    } else if scale > 1.0 {
+        linear_weights((new_position as f32 + 0.5) / scale - 0.5, length).to_vec()
+    } else {
+        let (start, end) = sample_window(new_position, scale, length);
+        let weight = 1.0 / (end - start) as f32;
+        (start..end).map(|position| (position, weight)).collect()
+    }
+}
+
+/// The two source pixels around a fractional position along a single axis, where pixel centers lie on integer
+/// positions, along with their weights. Positions outside of the axis are clamped to its edges.
+fn linear_weights(position : f32, length : u32) -> [(u32, f32); 2] {
+    let position = position.clamp(0.0, (length - 1) as f32);
+    let first = position.floor() as u32;
+    let second = (first + 1).min(length - 1);
+    let fraction = position - first as f32;
+    [(first, 1.0 - fraction), (second, fraction)]
+}
+
+/// Interpolates bilinearly the pixel at fractional coordinates, where pixel centers lie on integer coordinates.
+/// Coordinates outside of the image are clamped to its edges.
+pub fn sample_bilinear<S : ImageSource + ?Sized>(image : &S, x : f32, y : f32) -> Vec<u8> {
+    let horizontal_weights = linear_weights(x, image.get_width());
+    let vertical_weights = linear_weights(y, image.get_height());
+
+    let mut pixel = vec!(0.0; image.get_channels_per_pixel() as usize);
+    for (x, horizontal_weight) in &horizontal_weights {
+        for (y, vertical_weight) in &vertical_weights {
+            for (channel, value) in pixel.iter_mut().zip(image.get_pixel(*x, *y).iter()) {
+                *channel += horizontal_weight * vertical_weight * *value as f32;
+            }
+        }
+    }
+    pixel.iter().map(|channel| saturate_channel(channel.round())).collect()
+}
+
+/// Blurs an image using a separable Gaussian kernel of the passed standard deviation.
+/// Pixels outside the image are considered equal to the nearest edge pixel.
+pub fn gaussian_blur(image : &Image, sigma : f32) -> Result<Image> {
+    if sigma <= 0.0 || !sigma.is_finite() {
+        return Ok(image.clone());
+    }
+
+    let kernel = gaussian_kernel(sigma);
+    let horizontally_blurred = convolve(image, &kernel, (1, 0))?;
+    convolve(&horizontally_blurred, &kernel, (0, 1))
+}
+
+fn gaussian_kernel(sigma : f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights : Vec<f32> = (-radius..=radius).
+        map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp()).
+        collect();
+    let sum : f32 = weights.iter().sum();
+    weights.iter().map(|weight| weight / sum).collect()
+}
+
+fn convolve(image : &Image, kernel : &[f32], (step_x, step_y) : (i64, i64)) -> Result<Image> {
+    let radius = (kernel.len() / 2) as i64;
+    let max_x = image.get_width() as i64 - 1;
+    let max_y = image.get_height() as i64 - 1;
+
+    let mut data = Vec::new();
+    for y in 0..image.get_height() as i64 {
+        for x in 0..image.get_width() as i64 {
+            let mut pixel = vec!(0.0; image.get_channels_per_pixel() as usize);
+            for (index, weight) in kernel.iter().enumerate() {
+                let offset = index as i64 - radius;
+                let sample_x = (x + offset * step_x).clamp(0, max_x);
+                let sample_y = (y + offset * step_y).clamp(0, max_y);
+                let sample = image.get_pixel(sample_x as u32, sample_y as u32);
+                for (channel, value) in pixel.iter_mut().zip(sample.iter()) {
+                    *channel += weight * *value as f32;
+                }
+            }
+            data.extend(pixel.iter().map(|channel| saturate_channel(channel.round())));
         }
     }
 
-    for i in 0..channels_per_pixel {
-        average_pixel[i as usize] = (average_pixel[i as usize] as f32 / pixels.len() as f32).floor() as u32;
+    Image::from(&data, image.get_width(), image.get_channels_per_pixel())
+}
+
+/// How multi-channel images are converted to grayscale
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum GrayscaleMode {
+    /// Average of all channels. Images of two channels are treated as luma with alpha, keeping their luma.
+    Average,
+    /// Luma of the first three channels, treated as RGB, with the ITU-R BT.601 weights.
+    /// Images of two channels are treated as luma with alpha, keeping their luma.
+    Luma,
+    /// The channel of the passed index, e.g. 1 for the green channel of an RGB image
+    Channel(usize)
+}
+
+/// Converts an image to grayscale using the passed mode. The returned image only has a single channel.
+/// Single channel images are returned unchanged, for other images the channel of `GrayscaleMode::Channel` should exist.
+/// Averages of channels are rounded with `rounding`, while lumas are always rounded to the nearest integer.
+pub fn into_grayscale_with<S : ImageSource>(image : S, mode : GrayscaleMode, rounding : RoundingMode) -> Result<Image> {
+    let channels_per_pixel = image.get_channels_per_pixel() as usize;
+    if channels_per_pixel == 1 {
+        return image.into_image();
+    }
+
+    if let GrayscaleMode::Channel(channel) = mode {
+        if channel >= channels_per_pixel {
+            return Err(ImgcmpError::InvalidChannel { channel, channels_per_pixel });
+        }
+    }
+
+    let to_luma = |pixel : &[u8]| match mode {
+        GrayscaleMode::Channel(channel) => pixel[channel],
+        // Averaging alpha into luma would darken transparent pixels
+        GrayscaleMode::Average | GrayscaleMode::Luma if channels_per_pixel == 2 => pixel[0],
+        GrayscaleMode::Luma if channels_per_pixel >= 3 => {
+            let luma = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+            saturate_channel(luma.round())
+        },
+        GrayscaleMode::Average | GrayscaleMode::Luma => {
+            let sum : u64 = pixel.iter().map(|x| *x as u64).sum();
+            rounding.divide(sum, pixel.len() as u64) as u8
+        }
+    };
+
+    let width = image.get_width();
+    let luma_pixels : Vec<u8> = (0..image.get_height()).
+        flat_map(|y| (0..width).map(move |x| (x, y))).
+        map(|(x, y)| to_luma(image.get_pixel(x, y))).
+        collect();
+    Image::from(&luma_pixels, width, 1)
+}
+
+/// Composites an image with an alpha channel onto an opaque background, i.e. RGBA images onto the passed
+/// RGB color and luma with alpha images onto its average. Images of other channels are returned unchanged.
+pub fn composite_onto(image : &Image, background : [u8; 3]) -> Result<Image> {
+    let blend = |foreground : u8, background : u8, alpha : u8| {
+        let alpha = alpha as f32 / u8::MAX as f32;
+        saturate_channel((foreground as f32 * alpha + background as f32 * (1.0 - alpha)).round())
+    };
+
+    match image.get_channels_per_pixel() {
+        4 => image.map(3, |pixel| (0..3).map(|channel| blend(pixel[channel], background[channel], pixel[3])).collect()),
+        2 => {
+            let background_luma = (background.iter().map(|c| *c as u32).sum::<u32>() / 3) as u8;
+            image.map(1, |pixel| vec!(blend(pixel[0], background_luma, pixel[1])))
+        },
+        _ => Ok(image.clone())
     }
-    average_pixel
 }
 
-/// Converts an image to grayscale by taking the average of all channels.
-/// The returned image only has a single channel.
-pub fn into_grayscale(mut image : Image) -> Image {
-    image.apply(|pixel| {
-        let sum : u32 = pixel.iter().map(|x| *x as u32).sum();
-        let average = (sum as f32 / pixel.len() as f32).floor() as u8;
+/// Composites the RGBA `foreground` over the RGBA `background` of the same dimensions with the source-over operator.
+/// Channels are not premultiplied, fully transparent results are transparent black.
+pub fn blend_over(background : &Image, foreground : &Image) -> Image {
+    Image::from_fn(background.get_width(), background.get_height(), 4, |x, y| {
+        let (back, front) = (background.get_pixel(x, y), foreground.get_pixel(x, y));
+        let front_alpha = front[3] as f32 / u8::MAX as f32;
+        let back_alpha = back[3] as f32 / u8::MAX as f32 * (1.0 - front_alpha);
+        let alpha = front_alpha + back_alpha;
+        if alpha == 0.0 {
+            return vec!(0; 4);
+        }
 
-        pixel.clear();
-        pixel.push(average);
-    });
+        let mut pixel : Vec<u8> = (0..3).
+            map(|channel| saturate_channel(((front[channel] as f32 * front_alpha + back[channel] as f32 * back_alpha) / alpha).round())).
+            collect();
+        pixel.push(saturate_channel((alpha * u8::MAX as f32).round()));
+        pixel
+    })
+}
 
-    image
+/// Converts a computed value to a channel, saturating values outside of the range of a channel.
+/// NaN is converted to 0, so that invalid values cannot reach the DCT. Fractions are truncated,
+/// values should be rounded beforehand.
+fn saturate_channel(value : f32) -> u8 {
+    if value.is_nan() { 0 } else { value.clamp(0.0, u8::MAX as f32) as u8 }
 }
 
 #[cfg(test)]
 mod tests {
+    mod saturate_channel {
+        use crate::image_processing::saturate_channel;
+
+        #[test]
+        fn clamp_extreme_values_to_channel_range() {
+            assert_eq!(saturate_channel(f32::NAN), 0);
+            assert_eq!(saturate_channel(f32::INFINITY), 255);
+            assert_eq!(saturate_channel(f32::NEG_INFINITY), 0);
+            assert_eq!(saturate_channel(1e20), 255);
+            assert_eq!(saturate_channel(256.0), 255);
+            assert_eq!(saturate_channel(-0.5), 0);
+            assert_eq!(saturate_channel(127.9), 127);
+        }
+    }
+
     mod scale_image {
-        use crate::image_processing::scale_image;
-        use crate::Image;
+        use crate::image_processing::{scale_image, scale_image_with, Interpolation, RoundingMode, ScaleMode};
+        use crate::{Image, ImgcmpError};
 
         #[test]
         fn return_original_image_when_already_in_passed_dimensions() -> anyhow::Result<()> {
@@ -141,11 +530,11 @@ mod tests {
 
             let scaled_image = scale_image(&source_image, 2, 4)?;
 
-            let expected_image = Image::from_rgb(&vec!(
+            let expected_image = Image::from_rgb(&[
                 (60, 175, 65), (255, 10, 0),
                 (177, 105, 25), (177, 105, 25),
                 (60, 175, 65), (100, 200, 50),
-                (60, 175, 65), (100, 200, 50)), 2)?;
+                (60, 175, 65), (100, 200, 50)], 2)?;
             assert_eq!(scaled_image, expected_image);
             Ok(())
         }
@@ -178,7 +567,21 @@ mod tests {
         }
 
         #[test]
-        fn increase_both_dimensions() -> anyhow::Result<()> {
+        fn keep_hard_edges_when_decreasing_dimensions_with_point_sampling() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                0, 255, 0, 255,
+                0, 255, 0, 255], 4)?;
+
+            let area_image = scale_image_with(&source_image, 2, 1, Interpolation::Bilinear, ScaleMode::Area, RoundingMode::Floor)?;
+            let point_image = scale_image_with(&source_image, 2, 1, Interpolation::Bilinear, ScaleMode::Point, RoundingMode::Floor)?;
+
+            assert_eq!(area_image, Image::from_luma(&[127, 127], 2)?);
+            assert_eq!(point_image, Image::from_luma(&[255, 255], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn duplicate_pixels_when_increasing_both_dimensions_with_nearest_interpolation() -> anyhow::Result<()> {
             let color1 = (100, 200, 50, 200);
             let color2 = (20, 150, 80, 255);
             let color3 = (255, 10, 0, 0);
@@ -188,7 +591,7 @@ mod tests {
                 color3, color4);
             let source_image = Image::from_rgba(&raw_data, 2)?;
 
-            let scaled_image = scale_image(&source_image, 4, 4)?;
+            let scaled_image = scale_image_with(&source_image, 4, 4, Interpolation::Nearest, ScaleMode::Area, RoundingMode::Floor)?;
 
             assert_eq!(scaled_image.get_width(), 4);
             assert_eq!(scaled_image.get_height(), 4);
@@ -211,6 +614,33 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        fn blend_pixels_when_increasing_both_dimensions() -> anyhow::Result<()> {
+            let color1 = (100, 200, 50, 200);
+            let color2 = (20, 150, 80, 255);
+            let color3 = (255, 10, 0, 0);
+            let color4 = (80, 80, 80, 100);
+            let raw_data = vec!(
+                color1, color2,
+                color3, color4);
+            let source_image = Image::from_rgba(&raw_data, 2)?;
+
+            let scaled_image = scale_image(&source_image, 4, 4)?;
+
+            assert_eq!(scaled_image.get_width(), 4);
+            assert_eq!(scaled_image.get_height(), 4);
+            // Corners keep the source pixels
+            assert_eq!(*scaled_image.get_pixel(0, 0), vec!(100, 200, 50, 200));
+            assert_eq!(*scaled_image.get_pixel(3, 3), vec!(80, 80, 80, 100));
+            // 0.75 * color1 + 0.25 * color2
+            assert_eq!(*scaled_image.get_pixel(1, 0), vec!(80, 188, 58, 214));
+            // 0.5625 * color1 + 0.1875 * color2 + 0.1875 * color3 + 0.0625 * color4
+            assert_eq!(*scaled_image.get_pixel(1, 1), vec!(113, 148, 48, 167));
+            assert_ne!(scaled_image.get_pixel(1, 1), scaled_image.get_pixel(0, 0));
+            assert_ne!(scaled_image.get_pixel(2, 2), scaled_image.get_pixel(3, 3));
+            Ok(())
+        }
+
         #[test]
         fn reduce_to_not_exactly_divisible_dimensions() -> anyhow::Result<()> {
             let color1 = (100, 200, 50);
@@ -239,6 +669,19 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        fn keep_windows_inside_image_when_scale_factor_is_not_exact() -> anyhow::Result<()> {
+            // 22 / (13 / 22) is slightly above 22 in floats
+            let source_image = Image::from_luma(&[7; 22 * 22], 22)?;
+
+            let scaled_image = scale_image(&source_image, 13, 13)?;
+            let mixed_image = scale_image_with(&source_image, 13, 30, Interpolation::Bilinear, ScaleMode::Area, RoundingMode::Floor)?;
+
+            assert_eq!(scaled_image, Image::from_luma(&[7; 13 * 13], 13)?);
+            assert_eq!(mixed_image, Image::from_luma(&[7; 13 * 30], 13)?);
+            Ok(())
+        }
+
         #[test]
         fn return_error_when_passed_dimensions_are_zero() -> anyhow::Result<()> {
             let color1 = (100, 200, 50);
@@ -246,18 +689,277 @@ mod tests {
             let source_image = Image::from_rgb(&raw_data, 1)?;
 
             let result = scale_image(&source_image, 0, 1);
-            assert!(result.is_err());
+            assert_eq!(result, Err(ImgcmpError::ScaleError { width : 0, height : 1 }));
             let result = scale_image(&source_image, 1, 0);
-            assert!(result.is_err());
+            assert_eq!(result, Err(ImgcmpError::ScaleError { width : 1, height : 0 }));
             let result = scale_image(&source_image, 0, 0);
-            assert!(result.is_err());
+            assert_eq!(result, Err(ImgcmpError::ScaleError { width : 0, height : 0 }));
             Ok(())
         }
     }
 
-    mod into_grayscale {
-        use crate::image_processing::into_grayscale;
+    mod sub_sample {
+        use crate::{Image, ImgcmpError};
+        use crate::image_processing::{sample_pixels, scale_image, sub_sample, RoundingMode};
+
+        #[test]
+        fn average_disjoint_blocks() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                1, 2, 3, 4,
+                5, 6, 7, 8,
+                9, 10, 11, 12,
+                13, 14, 15, 17], 4)?;
+
+            let expected_image = Image::from_luma(&[
+                3, 5,
+                11, 13], 2)?;
+            assert_eq!(sub_sample(&source_image, 2, 2, RoundingMode::Floor)?, expected_image);
+            assert_eq!(scale_image(&source_image, 2, 2)?, expected_image);
+            assert_eq!(sub_sample(&source_image, 2, 2, RoundingMode::Ceil)?, Image::from_luma(&[4, 6, 12, 14], 2)?);
+            assert_eq!(sub_sample(&source_image, 4, 1, RoundingMode::Floor)?, Image::from_luma(&[2, 6, 10, 14], 1)?);
+            Ok(())
+        }
+
+        #[test]
+        fn do_not_overlap_blocks_of_factors_without_exact_float_inverse() -> anyhow::Result<()> {
+            let source_image = Image::from_fn(14, 1, 1, |x, _| vec!(if x < 7 { 0 } else { 70 }));
+
+            let scaled_image = scale_image(&source_image, 2, 1)?;
+
+            assert_eq!(scaled_image, Image::from_luma(&[0, 70], 2)?);
+            // The float window of the second pixel starts at the 7th pixel instead of the 8th
+            assert_eq!(sample_pixels(&source_image, 1, 0, 2.0 / 14.0, 1.0, RoundingMode::Floor), vec!(61.0));
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_factors_not_dividing_dimensions() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[1, 2, 3, 4, 5, 6], 3)?;
+
+            assert!(matches!(sub_sample(&source_image, 2, 1, RoundingMode::Floor), Err(ImgcmpError::InvalidDimensions(_))));
+            assert!(matches!(sub_sample(&source_image, 3, 0, RoundingMode::Floor), Err(ImgcmpError::InvalidDimensions(_))));
+            Ok(())
+        }
+    }
+
+    mod scale_image_fit {
         use crate::Image;
+        use crate::image_processing::{fit_dimensions, scale_image, scale_image_fit};
+
+        #[test]
+        fn scale_longer_edge_to_max_edge() -> anyhow::Result<()> {
+            let source_image = Image::from_fn(200, 100, 1, |x, y| vec!((x + y) as u8));
+
+            let fitted_image = scale_image_fit(&source_image, 50)?;
+
+            assert_eq!((fitted_image.get_width(), fitted_image.get_height()), (50, 25));
+            assert_eq!(fitted_image, scale_image(&source_image, 50, 25)?);
+            Ok(())
+        }
+
+        #[test]
+        fn preserve_aspect_ratio() {
+            assert_eq!(fit_dimensions(200, 100, 50), (50, 25));
+            assert_eq!(fit_dimensions(100, 200, 50), (25, 50));
+            assert_eq!(fit_dimensions(30, 30, 100), (100, 100));
+            assert_eq!(fit_dimensions(640, 480, 100), (100, 75));
+            assert_eq!(fit_dimensions(1000, 1, 10), (10, 1));
+        }
+    }
+
+    mod average_pixels {
+        use crate::image_processing::{average_pixels, RoundingMode};
+
+        #[test]
+        fn round_average_with_rounding_mode() {
+            let pixels = [&[10, 0, 7][..], &[11, 1, 7], &[11, 0, 8]];
+
+            assert_eq!(average_pixels(pixels.iter().copied(), RoundingMode::Floor), vec!(10.0, 0.0, 7.0));
+            assert_eq!(average_pixels(pixels.iter().copied(), RoundingMode::Round), vec!(11.0, 0.0, 7.0));
+            assert_eq!(average_pixels(pixels.iter().copied(), RoundingMode::Ceil), vec!(11.0, 1.0, 8.0));
+            assert_eq!(average_pixels(vec!(&[10][..], &[11]), RoundingMode::Round), vec!(11.0));
+        }
+
+        #[test]
+        fn average_window_whose_sum_overflows_u32() {
+            let white_pixel = [255, 255];
+            let dark_pixel = [0, 1];
+            // Repeats the pixels without allocating a window of 17M pixels
+            let white_pixels = || std::iter::repeat_n(&white_pixel[..], 17_000_000);
+
+            assert_eq!(average_pixels(white_pixels().chain(std::iter::once(&dark_pixel[..])), RoundingMode::Floor), vec!(254.0, 254.0));
+            assert_eq!(average_pixels(white_pixels(), RoundingMode::Floor), vec!(255.0, 255.0));
+        }
+    }
+
+    mod scale_image_masked {
+        use crate::image_processing::{scale_image, scale_image_masked, RoundingMode};
+        use crate::{Image, ImgcmpError};
+
+        #[test]
+        fn exclude_masked_pixels_from_averages() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[
+                10, 250, 30, 40,
+                10, 10, 50, 60], 4)?;
+            let mask = [
+                false, true, true, true,
+                false, false, true, true];
+
+            let scaled_image = scale_image_masked(&source_image, &mask, 2, 1, RoundingMode::Floor)?;
+
+            assert_eq!(scaled_image, Image::from_luma(&[10, 0], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn scale_like_scale_image_without_masked_pixels() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80), (255, 10, 0), (5, 0, 10)], 2)?;
+
+            let scaled_image = scale_image_masked(&source_image, &[false; 4], 1, 1, RoundingMode::Floor)?;
+
+            assert_eq!(scaled_image, scale_image(&source_image, 1, 1)?);
+            Ok(())
+        }
+
+        #[test]
+        fn keep_windows_inside_image_when_scale_factor_is_not_exact() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[7; 22 * 22], 22)?;
+
+            let scaled_image = scale_image_masked(&source_image, &[false; 22 * 22], 13, 13, RoundingMode::Floor)?;
+
+            assert_eq!(scaled_image, Image::from_luma(&[7; 13 * 13], 13)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_mask_of_wrong_size() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[1, 2, 3, 4], 2)?;
+
+            let result = scale_image_masked(&source_image, &[false; 3], 1, 1, RoundingMode::Floor);
+
+            assert_eq!(result, Err(ImgcmpError::BufferLengthMismatch { expected : 4, actual : 3 }));
+            Ok(())
+        }
+    }
+
+    mod scale_image_alpha_weighted {
+        use crate::image_processing::{scale_image_alpha_weighted, scale_image_masked, RoundingMode};
+        use crate::Image;
+
+        #[test]
+        fn weight_colors_by_their_alpha() -> anyhow::Result<()> {
+            let source_image = Image::from_rgba(&[
+                (200, 100, 0, 255), (0, 250, 50, 85), (10, 10, 10, 0), (10, 10, 10, 0),
+                (200, 100, 0, 255), (0, 250, 50, 85), (10, 10, 10, 0), (10, 10, 10, 0)], 4)?;
+
+            let scaled_image = scale_image_alpha_weighted(&source_image, None, 2, 1, RoundingMode::Floor)?;
+
+            // (255 * 200 + 85 * 0) / 340 = 150, (255 * 100 + 85 * 250) / 340 = 137, (85 * 50) / 340 = 12
+            assert_eq!(scaled_image, Image::from_rgb(&[(150, 137, 12), (0, 0, 0)], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn weight_luma_by_its_alpha_excluding_masked_pixels() -> anyhow::Result<()> {
+            let source_image = Image::from(&[
+                100, 255, 200, 51, 250, 255,
+                100, 255, 200, 51, 0, 0], 3, 2)?;
+            let mask = [false, false, true, false, false, true];
+
+            let scaled_image = scale_image_alpha_weighted(&source_image, Some(&mask), 1, 1, RoundingMode::Floor)?;
+
+            // (255 * 100 + 51 * 200 + 255 * 100 + 51 * 200) / 612 = 116
+            assert_eq!(scaled_image, Image::from_luma(&[116], 1)?);
+            Ok(())
+        }
+
+        #[test]
+        fn weight_pixels_without_alpha_equally() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (20, 150, 80), (255, 10, 0), (5, 0, 10)], 2)?;
+
+            let scaled_image = scale_image_alpha_weighted(&source_image, None, 1, 1, RoundingMode::Floor)?;
+
+            assert_eq!(scaled_image, scale_image_masked(&source_image, &[false; 4], 1, 1, RoundingMode::Floor)?);
+            Ok(())
+        }
+    }
+
+    mod gaussian_blur {
+        use crate::image_processing::gaussian_blur;
+        use crate::Image;
+
+        #[test]
+        fn keep_uniform_image_unchanged() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[80; 25], 5)?;
+
+            let blurred_image = gaussian_blur(&source_image, 1.5)?;
+
+            assert_eq!(blurred_image, source_image);
+            Ok(())
+        }
+
+        #[test]
+        fn spread_single_pixel_symmetrically() -> anyhow::Result<()> {
+            let mut raw_data = vec!(0; 25);
+            raw_data[12] = 255;
+            let source_image = Image::from_luma(&raw_data, 5)?;
+
+            let blurred_image = gaussian_blur(&source_image, 1.0)?;
+
+            let center = blurred_image.get_pixel(2, 2)[0];
+            assert!(center < 255);
+            assert!(blurred_image.get_pixel(1, 2)[0] < center);
+            assert_eq!(blurred_image.get_pixel(1, 2), blurred_image.get_pixel(3, 2));
+            assert_eq!(blurred_image.get_pixel(2, 1), blurred_image.get_pixel(2, 3));
+            assert_eq!(blurred_image.get_pixel(1, 2), blurred_image.get_pixel(2, 1));
+            Ok(())
+        }
+
+        #[test]
+        fn return_original_image_when_sigma_is_zero() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[0, 255, 0, 255], 2)?;
+
+            assert_eq!(gaussian_blur(&source_image, 0.0)?, source_image);
+            Ok(())
+        }
+    }
+
+    mod composite_onto {
+        use crate::Image;
+        use crate::image_processing::composite_onto;
+
+        #[test]
+        fn blend_rgba_pixels_with_background_by_their_alpha() -> anyhow::Result<()> {
+            let source_image = Image::from_rgba(&[(10, 20, 30, 255), (10, 20, 30, 0), (100, 0, 200, 51)], 3)?;
+
+            let composited_image = composite_onto(&source_image, [255, 255, 0])?;
+
+            assert_eq!(composited_image, Image::from_rgb(&[(10, 20, 30), (255, 255, 0), (224, 204, 40)], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn blend_luma_alpha_pixels_with_background_luma() -> anyhow::Result<()> {
+            let source_image = Image::from(&[0, 0, 200, 255], 2, 2)?;
+
+            let composited_image = composite_onto(&source_image, [90, 90, 90])?;
+
+            assert_eq!(composited_image, Image::from_luma(&[90, 200], 2)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_images_without_alpha_unchanged() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(10, 20, 30), (40, 50, 60)], 2)?;
+
+            assert_eq!(composite_onto(&source_image, [255, 255, 255])?, source_image);
+            Ok(())
+        }
+    }
+
+    mod into_grayscale {
+        use crate::image_processing::{into_grayscale_with, GrayscaleMode, RoundingMode};
+        use crate::{Image, ImgcmpError};
 
         #[test]
         fn return_average_of_all_channels() -> anyhow::Result<()> {
@@ -270,8 +972,9 @@ mod tests {
                 color1, color2, color1);
             let source_image = Image::from_rgb(&raw_data, 3)?;
 
-            let scaled_image = into_grayscale(source_image);
+            let scaled_image = into_grayscale_with(source_image, GrayscaleMode::Average, RoundingMode::Floor)?;
 
+            assert_eq!(scaled_image.get_channels_per_pixel(), 1);
             assert_eq!(scaled_image.get_width(), 3);
             assert_eq!(scaled_image.get_height(), 3);
             assert_eq!(*scaled_image.get_pixel(0, 0), vec!(116));
@@ -285,5 +988,69 @@ mod tests {
             assert_eq!(*scaled_image.get_pixel(2, 2), vec!(116));
             Ok(())
         }
+
+        #[test]
+        fn return_luma_of_rgb_channels() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (255, 0, 0), (0, 0, 255)], 3)?;
+
+            let grayscale_image = into_grayscale_with(source_image, GrayscaleMode::Luma, RoundingMode::Floor)?;
+
+            assert_eq!(grayscale_image, Image::from_luma(&[153, 76, 29], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_selected_channel() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (255, 0, 0), (0, 0, 255)], 3)?;
+
+            let grayscale_image = into_grayscale_with(source_image.clone(), GrayscaleMode::Channel(1), RoundingMode::Floor)?;
+
+            assert_eq!(grayscale_image, Image::from_luma(&[200, 0, 0], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn round_average_of_channels_with_rounding_mode() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(10, 10, 11), (10, 11, 11), (0, 0, 3)], 3)?;
+
+            let grayscale_image = |rounding| into_grayscale_with(source_image.clone(), GrayscaleMode::Average, rounding);
+
+            assert_eq!(grayscale_image(RoundingMode::Floor)?, Image::from_luma(&[10, 10, 1], 3)?);
+            assert_eq!(grayscale_image(RoundingMode::Round)?, Image::from_luma(&[10, 11, 1], 3)?);
+            assert_eq!(grayscale_image(RoundingMode::Ceil)?, Image::from_luma(&[11, 11, 1], 3)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_error_for_missing_channel() -> anyhow::Result<()> {
+            let source_image = Image::from_rgb(&[(100, 200, 50), (255, 0, 0), (0, 0, 255)], 3)?;
+
+            let result = into_grayscale_with(source_image, GrayscaleMode::Channel(3), RoundingMode::Floor);
+
+            assert_eq!(result, Err(ImgcmpError::InvalidChannel { channel : 3, channels_per_pixel : 3 }));
+            Ok(())
+        }
+
+        #[test]
+        fn keep_luma_of_luma_alpha_images() -> anyhow::Result<()> {
+            let source_image = Image::from(&[10, 255, 20, 0, 30, 128], 3, 2)?;
+
+            let average_image = into_grayscale_with(source_image.clone(), GrayscaleMode::Average, RoundingMode::Floor)?;
+            let luma_image = into_grayscale_with(source_image.clone(), GrayscaleMode::Luma, RoundingMode::Floor)?;
+
+            assert_eq!(average_image, Image::from_luma(&[10, 20, 30], 3)?);
+            assert_eq!(luma_image, source_image.channel(0)?);
+            Ok(())
+        }
+
+        #[test]
+        fn return_original_image_when_already_grayscale() -> anyhow::Result<()> {
+            let source_image = Image::from_luma(&[10, 20, 30, 40, 50, 60], 3)?;
+
+            let grayscale_image = into_grayscale_with(source_image.clone(), GrayscaleMode::Luma, RoundingMode::Floor)?;
+
+            assert_eq!(grayscale_image, source_image);
+            Ok(())
+        }
     }
 }
\ No newline at end of file
@@ -1,54 +1,618 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod core;
+mod hash;
+#[cfg(feature = "std")]
 mod image;
+#[cfg(feature = "std")]
 mod image_processing;
+#[cfg(feature = "std")]
 mod dct;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod hasher;
+#[cfg(feature = "std")]
+mod comparator;
+#[cfg(feature = "cache")]
+mod cache;
+#[cfg(feature = "config-file")]
+mod config_file;
+#[cfg(feature = "image")]
+mod exif;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+/// The most commonly used items, for importing them all with `use imgcmp_lib::prelude::*`.
+/// `Result` is left out, so that it does not shadow the one of the standard library.
+#[cfg(feature = "std")]
+pub mod prelude {
+    pub use crate::{compare_images, Comparator, Config, DistanceMetric, Image, ImageHash, ImgcmpError, PerceptualHasher};
+}
 
-pub use crate::image::Image;
-use anyhow::Context;
-use nalgebra::DMatrix;
-use ndarray::Array2;
+/// Internal functions exposed only for benchmarks, not part of the public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::dct::{calc_dct_basis, calc_dct_coefficients};
+    #[cfg(feature = "rayon")]
+    pub use crate::dct::calc_dct_coefficients_parallel;
+}
+
+pub use crate::hash::{DistanceMetric, ImageHash, diff_bits};
+pub use crate::core::{CoefficientOrder, ThresholdStrategy, TieBreaking};
+#[cfg(feature = "std")]
+pub use crate::image::{Image, ImageSource, ImageView};
+#[cfg(feature = "std")]
+pub use crate::error::{ImgcmpError, Result};
+#[cfg(feature = "std")]
+pub use crate::dct::hash_bits;
+#[cfg(feature = "std")]
+pub use crate::hasher::{DistanceBreakdown, HashDetails, PerceptualHasher};
+#[cfg(feature = "std")]
+pub use crate::comparator::{CachingComparator, Comparator};
+#[cfg(feature = "std")]
+pub use crate::image_processing::{GrayscaleMode, Interpolation, RoundingMode, ScaleMode};
+#[cfg(feature = "cache")]
+pub use crate::cache::HashCache;
+
+#[cfg(feature = "std")]
+/// What happens when hashing an image smaller than `Config::min_source_size`
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+pub enum SmallImagePolicy {
+    /// Hash the image, logging a warning that the hash may be unreliable
+    Warn,
+    /// Fail with `ImgcmpError::ImageTooSmall`
+    Reject
+}
 
+#[cfg(feature = "std")]
+/// Coarse classification of how close the hashes of two images are
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchClass {
+    /// Hashes are equal
+    Identical,
+    /// Hashes are within the allowed distance, so the images are considered the same
+    NearDuplicate,
+    /// Hashes are within twice the allowed distance
+    Similar,
+    /// Hashes are further than twice the allowed distance
+    Different
+}
+
+#[cfg(feature = "std")]
+#[derive (Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "config-file", derive (serde::Deserialize))]
+#[cfg_attr(feature = "config-file", serde(default, deny_unknown_fields))]
 pub struct Config {
     /// Dimension of DCT matrix, usually 32x32
     pub dct_dimension : u32,
+    /// When non empty, `compare_images` hashes images at each of these DCT dimensions instead of `dct_dimension`,
+    /// e.g. `vec!(16, 32)`, and compares the concatenation of their hashes. Images are then the same when the
+    /// sum of the distances of their hashes is within the allowed distance times the number of dimensions.
+    pub dct_dimensions : Vec<u32>,
     /// Dimension of reduced DCT matrix, e.g. when 8 we will keep only the top left 8x8 corner of DCT
     pub dct_reduced_dimension : u32,
+    /// Number of (horizontal, vertical) frequencies of a non square reduced DCT matrix.
+    /// Overrides `dct_reduced_dimension` when set. Their product should not exceed 64.
+    pub dct_reduced_shape : Option<(u32, u32)>,
+    /// Order of the coefficients of the reduced DCT matrix in the bits of the hash
+    pub coefficient_order : CoefficientOrder,
+    /// Number of bits of the hash, at most 64. When set, the lowest frequency coefficients are kept
+    /// in JPEG zig-zag order, overriding `dct_reduced_dimension` and `dct_reduced_shape`.
+    pub hash_bits : Option<u32>,
+    /// Whether the coefficients of the first row and column of the DCT, i.e. of purely horizontal or purely
+    /// vertical frequencies, are excluded from the hash, making it insensitive to brightness changes
+    /// along a single direction, at the cost of fewer bits, e.g. 49 instead of 64 for an 8x8 reduction.
+    pub exclude_first_row_col : bool,
     /// Maximum Hamming distance between two hashes for considering two images as equal
-    pub allowed_distance : u8
+    pub allowed_distance : u8,
+    /// When set, only the bits of the coefficients of the lowest `n`x`n` frequencies are compared, which
+    /// makes comparisons robust to the high frequency artifacts of JPEG compression. Hashing is unaffected.
+    pub low_freq_only : Option<u32>,
+    /// How the distance between two hashes is measured. `DistanceMetric::NormalizedHamming` carries
+    /// its own threshold, replacing `allowed_distance`.
+    pub distance_metric : DistanceMetric,
+    /// How the threshold separating the 0 bits from the 1 bits of the hash is derived from the coefficients
+    pub threshold : ThresholdStrategy,
+    /// Width of the "dead zone" around the threshold. Coefficients closer than this
+    /// to the threshold are always mapped to 0, so that floating point noise cannot flip their bit.
+    /// Zero disables the dead zone.
+    pub threshold_tolerance : f32,
+    /// How coefficients exactly equal to the threshold are mapped. Defaults to `StrictlyAbove`,
+    /// so that uniform images, whose coefficients all equal the threshold, hash to all zeros.
+    pub tie_breaking : TieBreaking,
+    /// Tolerance of the uniform borders removed before hashing, so that images match their versions
+    /// with solid borders, see `Image::trim_border`. `None` keeps the borders.
+    pub trim_border : Option<u8>,
+    /// Background color on which images with an alpha channel are composited before hashing, so that
+    /// transparent images match their versions on an opaque background. `None` keeps the alpha channel,
+    /// which is then treated as any other channel.
+    pub composite_background : Option<[u8; 3]>,
+    /// Whether images with alpha are scaled down weighted by it, see `scale_image_alpha_weighted`. Ignored with `composite_background`
+    pub alpha_weighting : bool,
+    /// Standard deviation of a Gaussian blur applied on the grayscale image before scaling it down,
+    /// which makes the hash more robust to high frequency noise. `None` disables blurring.
+    pub blur_sigma : Option<f32>,
+    /// How color images are converted to grayscale before hashing
+    pub grayscale_mode : GrayscaleMode,
+    /// Whether the histogram of the grayscale image is equalized before scaling it down, which makes
+    /// the hash robust to contrast and brightness differences
+    pub equalize_histogram : bool,
+    /// Images with a variance below this, see `Image::variance`, are considered low information by
+    /// `PerceptualHasher::is_low_information`. Their hashes are degenerate, e.g. all solid colors hash the same.
+    pub min_variance : f32,
+    /// Minimum width and height of hashed images. Smaller images have to be scaled up to `dct_dimension`,
+    /// which makes their hashes unreliable. `None` accepts images of any size.
+    pub min_source_size : Option<u32>,
+    /// What happens when hashing an image smaller than `min_source_size`
+    pub small_image_policy : SmallImagePolicy,
+    /// Interpolation used when images smaller than `dct_dimension` are scaled up
+    pub upscale_interpolation : Interpolation,
+    /// Sampling used when images larger than `dct_dimension` are scaled down. Masked hashes are always area averaged.
+    pub downscale_mode : ScaleMode,
+    /// How area averages when scaling down and `GrayscaleMode::Average` averages are rounded, see `RoundingMode`
+    pub rounding_mode : RoundingMode,
+    /// Maximum difference between the aspect ratios (width / height) of two images compared by `compare_images`.
+    /// Images differing more are rejected without hashing them. `None` disables the check.
+    pub max_aspect_ratio_diff : Option<f32>,
+    /// Number of overlapping tiles per row and column hashed by `compare_images_multiscale`
+    pub multiscale_grid : u32,
+    /// Minimum number of matching region hashes for `compare_images_multiscale` to consider two images as equal
+    pub multiscale_min_matching_tiles : u32
+}
+
+#[cfg(feature = "std")]
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            dct_dimension : 32,
+            dct_dimensions : vec!(),
+            dct_reduced_dimension : 8,
+            dct_reduced_shape : None,
+            coefficient_order : CoefficientOrder::Block,
+            hash_bits : None,
+            exclude_first_row_col : false,
+            allowed_distance : 3,
+            low_freq_only : None,
+            distance_metric : DistanceMetric::Hamming,
+            threshold : ThresholdStrategy::Mean,
+            threshold_tolerance : 0.0,
+            tie_breaking : TieBreaking::StrictlyAbove,
+            trim_border : None,
+            composite_background : None,
+            alpha_weighting : false,
+            blur_sigma : None,
+            grayscale_mode : GrayscaleMode::Average,
+            equalize_histogram : false,
+            min_variance : 1.0,
+            min_source_size : None,
+            small_image_policy : SmallImagePolicy::Warn,
+            upscale_interpolation : Interpolation::Bilinear,
+            downscale_mode : ScaleMode::Area,
+            rounding_mode : RoundingMode::Floor,
+            max_aspect_ratio_diff : None,
+            multiscale_grid : 3,
+            multiscale_min_matching_tiles : 1
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Config {
+    /// Only considers images with identical hashes as equal. There are no false positives in
+    /// practice, but even light edits or re-encoding may make an image different.
+    pub fn strict() -> Config {
+        Config { allowed_distance : 0, ..Config::default() }
+    }
+
+    /// Tolerates heavier edits, such as strong compression or color adjustments, at the cost
+    /// of occasionally matching unrelated images with similar structure.
+    pub fn lenient() -> Config {
+        Config { allowed_distance : 10, ..Config::default() }
+    }
+
+    /// Computes the DCT on a 16x16 image, which is roughly 16 times faster than the default 32x32.
+    /// Hashes are less robust to scaling and fine details, since less of the image is sampled.
+    pub fn fast() -> Config {
+        Config { dct_dimension : 16, ..Config::default() }
+    }
+
+    /// Cheap first stage of `compare_images_staged` for the default configuration, see `preview`.
+    pub fn fast_preview() -> Config {
+        Config::default().preview()
+    }
+
+    /// Cheap first stage of `compare_images_staged` for this configuration, sampling a 16x16 image with
+    /// `ScaleMode::Point` instead of averaging a `dct_dimension` one, at a single DCT dimension.
+    /// Its allowed distance is lenient, twice the allowed distance of this configuration and at least 12 bits out of 64,
+    /// so that only obvious non-matches are rejected. Other fields are kept, so that both stages hash the same
+    /// coefficients of the same preprocessed images.
+    pub fn preview(&self) -> Config {
+        const LENIENT_FRACTION : f32 = 12.0 / 64.0;
+        let lenient_distance = (LENIENT_FRACTION * self.hash_bit_count() as f32).round() as u8;
+        let distance_metric = match self.distance_metric {
+            DistanceMetric::NormalizedHamming { max_distance } =>
+                DistanceMetric::NormalizedHamming { max_distance : (2.0 * max_distance).max(LENIENT_FRACTION) },
+            DistanceMetric::Hamming => DistanceMetric::Hamming
+        };
+        let (reduced_width, reduced_height) = self.reduced_shape();
+        Config {
+            dct_dimension : self.dct_dimension.min(16).max(reduced_width).max(reduced_height),
+            dct_dimensions : vec!(),
+            downscale_mode : ScaleMode::Point,
+            allowed_distance : self.allowed_distance.saturating_mul(2).max(lenient_distance),
+            distance_metric,
+            ..self.clone()
+        }
+    }
+
+    /// Number of (horizontal, vertical) frequencies kept in the reduced DCT matrix
+    pub fn reduced_shape(&self) -> (u32, u32) {
+        self.dct_reduced_shape.unwrap_or((self.dct_reduced_dimension, self.dct_reduced_dimension))
+    }
+
+    /// Checks that the configuration can hash images, i.e. that its DCT dimensions are not zero and that
+    /// `hash_bits` is between 1 and 64, and that it can tell images apart, i.e. that `allowed_distance`
+    /// does not exceed the number of bits of the hash, which would consider all images as equal.
+    pub fn validate(&self) -> Result<()> {
+        if self.dct_dimension == 0 || self.dct_dimensions.contains(&0) {
+            return Err(ImgcmpError::InvalidConfig("DCT dimensions should not be zero".to_string()));
+        }
+        match self.hash_bits {
+            Some(bits) if bits == 0 || bits > 64 =>
+                return Err(ImgcmpError::InvalidConfig(format!("hash bits should be between 1 and 64 but are {}", bits))),
+            _ => ()
+        }
+
+        let bits = self.hash_bit_count();
+        if self.distance_metric == DistanceMetric::Hamming && self.allowed_distance as u32 > bits {
+            return Err(ImgcmpError::InvalidConfig(
+                format!("allowed distance {} exceeds the {} bits of the hash", self.allowed_distance, bits)));
+        }
+        Ok(())
+    }
+
+    /// Number of bits of the hashes computed with this configuration
+    pub fn hash_bit_count(&self) -> u32 {
+        self.coefficient_positions().len() as u32
+    }
+
+    /// Sets `allowed_distance` to the passed fraction of the bits of the hash, e.g. 0.05 for tolerating
+    /// 5% of differing bits, so that the threshold scales with the size of the hash.
+    /// Distances above 255 bits are clamped.
+    pub fn with_similarity_threshold(self, fraction : f32) -> Config {
+        let allowed_distance = (fraction * self.hash_bit_count() as f32).round().clamp(0.0, u8::MAX as f32) as u8;
+        Config { allowed_distance, ..self }
+    }
+
+    /// Weights of the bits of the hashes computed with this configuration for `core::compare_hashes_weighted`,
+    /// emphasizing the bits of the lowest frequencies
+    pub fn bit_weights(&self) -> Vec<f32> {
+        core::frequency_weights(&self.coefficient_positions())
+    }
+
+    /// (horizontal, vertical) frequencies of the DCT coefficients kept for hashing, in the order of their bits
+    pub(crate) fn coefficient_positions(&self) -> Vec<(usize, usize)> {
+        let is_kept = |(horizontal, vertical) : &(usize, usize)| {
+            !self.exclude_first_row_col || (*horizontal > 0 && *vertical > 0)
+        };
+        match self.hash_bits {
+            Some(bits) => {
+                let bits = bits as usize;
+                // The first `bits` zig-zag positions always fit in a `bits`x`bits` block,
+                // or in a block one larger when the first row and column are excluded
+                core::zigzag_positions(bits + 1, bits + 1).into_iter().filter(is_kept).take(bits).collect()
+            },
+            None => {
+                let (horizontal, vertical) = self.reduced_shape();
+                self.coefficient_order.positions(horizontal as usize, vertical as usize).into_iter().filter(is_kept).collect()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn compare_images(left_image : &Image, right_image : &Image, config : Config) -> Result<bool> {
+    config.validate()?;
+    if let Some(max_diff) = config.max_aspect_ratio_diff {
+        let aspect_ratio = |image : &Image| image.get_width() as f32 / image.get_height() as f32;
+        if (aspect_ratio(left_image) - aspect_ratio(right_image)).abs() > max_diff {
+            return Ok(false);
+        }
+    }
+
+    if !config.dct_dimensions.is_empty() {
+        return compare_images_multi_dimension(left_image, right_image, &config);
+    }
+    compare_images_with(left_image, right_image, &PerceptualHasher::new(config))
+}
+
+#[cfg(feature = "std")]
+/// Compares the concatenated hashes of `Config::dct_dimensions`, whose distance is the sum of the distances of each hash
+fn compare_images_multi_dimension(left_image : &Image, right_image : &Image, config : &Config) -> Result<bool> {
+    if left_image == right_image {
+        PerceptualHasher::new(Config { dct_dimensions : vec!(), ..config.clone() }).check_source_size(left_image)?;
+        return Ok(true);
+    }
+
+    let mut distance = 0.0;
+    let mut allowed_distance = 0.0;
+    for dct_dimension in &config.dct_dimensions {
+        let hasher = PerceptualHasher::new(Config { dct_dimension : *dct_dimension, dct_dimensions : vec!(), ..config.clone() });
+        distance += hasher.distance(&hasher.hash(left_image)?, &hasher.hash(right_image)?);
+        allowed_distance += hasher.allowed_distance();
+    }
+    log::debug!("Hashes of DCT dimensions {:?} are at distance {}, allowed distance is {}", config.dct_dimensions, distance, allowed_distance);
+    Ok(distance <= allowed_distance)
 }
 
-pub fn compare_images(left_image : &Image, right_image : &Image, config : Config) -> anyhow::Result<bool> {
-    let dct_basis_signals = dct::calc_dct_basis(config.dct_dimension);
-    let left_hash = hash_image(&left_image, &dct_basis_signals, config.dct_reduced_dimension).
-        context("Failed to create hash for first image")?;
-    let right_hash = hash_image(&right_image, &dct_basis_signals, config.dct_reduced_dimension).
-        context("Failed to create hash for second image")?;
+#[cfg(feature = "std")]
+/// Compares two images using the hashing algorithm of the passed comparator.
+/// Byte-for-byte equal images are considered the same without hashing them, unless `Comparator::check_image` rejects them.
+pub fn compare_images_with(left_image : &Image, right_image : &Image, comparator : &dyn Comparator) -> Result<bool> {
+    if left_image == right_image {
+        comparator.check_image(left_image)?;
+        return Ok(true);
+    }
+
+    let left_hash = comparator.hash(left_image)?;
+    let right_hash = comparator.hash(right_image)?;
 
-    // println!("{:#b}", left_hash);
-    // println!("{:#b}", right_hash);
-    let distance = dct::compare_hashes(left_hash, right_hash);
-    Ok(distance <= config.allowed_distance)
+    let are_same = comparator.are_same(&left_hash, &right_hash);
+    log::debug!("Hashes {:#018x} and {:#018x} are at distance {}, images are {}", left_hash.0, right_hash.0,
+                left_hash.distance(&right_hash), if are_same { "the same" } else { "different" });
+    Ok(are_same)
 }
 
-fn hash_image(image : &Image, dct_basis : &Array2<DMatrix<f32>>, dct_reduced_dimension : u32) -> anyhow::Result<u64> {
-    // Scale down to DCT size
-    let (dct_dimension, _) = dct_basis.dim();
-    let shrank_image = image_processing::
-        scale_image(image, dct_dimension as u32, dct_dimension as u32).
-        context("Failed to scale image")?;
+#[cfg(feature = "std")]
+/// Compares two images by hashing the whole image along with a grid of overlapping tiles of it.
+/// Images are considered equal when enough pairs of region hashes are within the allowed distance,
+/// which makes the comparison robust to cropping and zooming.
+pub fn compare_images_multiscale(left_image : &Image, right_image : &Image, config : Config) -> Result<bool> {
+    let hasher = PerceptualHasher::new(config);
+    let left_hashes = hash_regions(left_image, &hasher)?;
+    let right_hashes = hash_regions(right_image, &hasher)?;
+
+    let matching_regions = left_hashes.iter().
+        map(|left_hash| right_hashes.iter().filter(|right_hash| hasher.are_same(left_hash, right_hash)).count()).
+        sum::<usize>();
+    Ok(matching_regions >= hasher.get_config().multiscale_min_matching_tiles as usize)
+}
 
-    // convert to grayscale
-    let shrank_grayscale_image = image_processing::into_grayscale(shrank_image);
+#[cfg(feature = "std")]
+fn hash_regions(image : &Image, hasher : &PerceptualHasher) -> Result<Vec<ImageHash>> {
+    let mut hashes = vec!(hasher.hash(image)?);
 
-    // compute NxN DCT coefficients
-    let dct_coefficients = dct::calc_dct_coefficients(&shrank_grayscale_image, &dct_basis);
-    let dct_reduced_coefficients = dct::reduce_dct_coefficients(dct_coefficients, dct_reduced_dimension);
+    // Tiles span two grid steps, so that consecutive tiles overlap by half
+    let grid = hasher.get_config().multiscale_grid;
+    let grid_steps = grid as u64 + 1;
+    let grid_position = |step : u32, length : u32| (step as u64 * length as u64 / grid_steps) as u32;
+    // Tiles of images smaller than the grid would be empty, they are at least a pixel wide instead
+    let tile_width = grid_position(2, image.get_width()).max(1);
+    let tile_height = grid_position(2, image.get_height()).max(1);
+    for row in 0..grid {
+        for column in 0..grid {
+            let tile = image.crop(grid_position(column, image.get_width()), grid_position(row, image.get_height()),
+                                  tile_width, tile_height)?;
+            hashes.push(hasher.hash(&tile)?);
+        }
+    }
 
-    // create hash
-    let hash = dct::hash_coefficients(&dct_reduced_coefficients).context("Failed to calculate hash")?;
-    Ok(hash)
+    Ok(hashes)
 }
 
-#[cfg(test)]
+#[cfg(feature = "std")]
+/// Splits the image into a grid of (columns, rows) cells and hashes each of them. Hashes are returned
+/// row by row, so comparing the hashes of two images locates the cells where they differ.
+pub fn hash_tiles(image : &Image, (columns, rows) : (u32, u32), config : &Config) -> Result<Vec<ImageHash>> {
+    if columns == 0 || rows == 0 || columns > image.get_width() || rows > image.get_height() {
+        return Err(ImgcmpError::InvalidDimensions(
+            format!("cannot split {}x{} image into {}x{} tiles", image.get_width(), image.get_height(), columns, rows)));
+    }
+
+    let hasher = PerceptualHasher::new(config.clone());
+    let mut hashes = Vec::new();
+    for row in 0..rows {
+        let (top, bottom) = cell_bounds(row, rows, image.get_height());
+        for column in 0..columns {
+            let (left, right) = cell_bounds(column, columns, image.get_width());
+            let tile = image.view(left, top, right - left, bottom - top)?;
+            hashes.push(hasher.hash_source(&tile)?);
+        }
+    }
+
+    Ok(hashes)
+}
+
+#[cfg(feature = "std")]
+/// Range of positions of the cell of index `cell` out of `cells` splitting `length` positions.
+/// Computed in 64 bits, since the products of cells and lengths overflow 32 bits.
+fn cell_bounds(cell : u32, cells : u32, length : u32) -> (u32, u32) {
+    let position = |cell : u32| (cell as u64 * length as u64 / cells as u64) as u32;
+    (position(cell), position(cell + 1))
+}
+
+#[cfg(feature = "std")]
+/// Detects scene cuts in a sequence of frames, such as the frames of a video or an animated GIF.
+/// Returns the indices of the frames whose hash is further than `threshold` from the hash of the previous frame.
+pub fn scene_cuts(frames : &[Image], config : &Config, threshold : u8) -> Result<Vec<usize>> {
+    let hasher = PerceptualHasher::new(config.clone());
+    let hashes = frames.iter().map(|frame| hasher.hash(frame)).collect::<Result<Vec<ImageHash>>>()?;
+
+    let cuts = hashes.windows(2).enumerate().
+        filter(|(_, pair)| pair[0].distance(&pair[1]) > threshold as u32).
+        map(|(index, _)| index + 1).
+        collect();
+    Ok(cuts)
+}
+
+#[cfg(feature = "std")]
+/// Finds the reference image closest to the query image. Returns the index of the reference
+/// and its distance from the query, or `None` when no reference is within the allowed distance.
+pub fn best_match(query : &Image, refs : &[Image], config : &Config) -> Result<Option<(usize, u32)>> {
+    let hasher = PerceptualHasher::new(config.clone());
+    let query_hash = hasher.hash(query)?;
+
+    let mut best = None;
+    for (index, reference) in refs.iter().enumerate() {
+        let reference_hash = hasher.hash(reference)?;
+        let distance = query_hash.distance(&reference_hash);
+        let is_closer = best.is_none_or(|(_, best_distance)| distance < best_distance);
+        if hasher.are_same(&query_hash, &reference_hash) && is_closer {
+            best = Some((index, distance));
+        }
+    }
+
+    Ok(best)
+}
+
+#[cfg(feature = "std")]
+/// Compares two images in two stages, e.g. for pre-filtering huge collections. Cheap hashes of
+/// `Config::preview` of the passed configuration reject obvious non-matches, and only the remaining
+/// images are compared with `compare_images` and the passed configuration.
+pub fn compare_images_staged(left_image : &Image, right_image : &Image, config : Config) -> Result<bool> {
+    if !compare_images(left_image, right_image, config.preview())? {
+        return Ok(false);
+    }
+    compare_images(left_image, right_image, config)
+}
+
+#[cfg(feature = "std")]
+/// Hashes both images and counts their differing bits per frequency band. See `DistanceBreakdown`.
+pub fn compare_images_breakdown(left_image : &Image, right_image : &Image, config : &Config) -> Result<DistanceBreakdown> {
+    let hasher = PerceptualHasher::new(config.clone());
+    Ok(hasher.distance_breakdown(&hasher.hash(left_image)?, &hasher.hash(right_image)?))
+}
+
+#[cfg(feature = "std")]
+/// Hashes both images and classifies how close they are. See `MatchClass`.
+pub fn classify(left_image : &Image, right_image : &Image, config : &Config) -> Result<MatchClass> {
+    let hasher = PerceptualHasher::new(config.clone());
+    Ok(hasher.classify(&hasher.hash(left_image)?, &hasher.hash(right_image)?))
+}
+
+#[cfg(feature = "std")]
+/// Groups near-duplicate images, i.e. images transitively within the allowed distance of each other.
+/// Returns the indices of the images of each group in ascending order, with groups ordered by their
+/// first index. Images without duplicates form groups of their own.
+pub fn cluster_duplicates(images : &[Image], config : &Config) -> Result<Vec<Vec<usize>>> {
+    let hasher = PerceptualHasher::new(config.clone());
+    let hashes = images.iter().map(|image| hasher.hash(image)).collect::<Result<Vec<ImageHash>>>()?;
+
+    fn root(parents : &mut [usize], mut index : usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+
+    let mut parents : Vec<usize> = (0..hashes.len()).collect();
+    for left in 0..hashes.len() {
+        for right in left + 1..hashes.len() {
+            if hasher.are_same(&hashes[left], &hashes[right]) {
+                let (left_root, right_root) = (root(&mut parents, left), root(&mut parents, right));
+                parents[left_root.max(right_root)] = left_root.min(right_root);
+            }
+        }
+    }
+
+    let mut clusters : Vec<Vec<usize>> = Vec::new();
+    let mut cluster_of_root = std::collections::HashMap::new();
+    for index in 0..hashes.len() {
+        let cluster = *cluster_of_root.entry(root(&mut parents, index)).or_insert_with(|| {
+            clusters.push(Vec::new());
+            clusters.len() - 1
+        });
+        clusters[cluster].push(index);
+    }
+    Ok(clusters)
+}
+
+#[cfg(feature = "std")]
+/// Picks the `allowed_distance` that best separates pairs of the same image from pairs of different images,
+/// i.e. the one classifying most pairs correctly with the other parameters of `config`. Among equally accurate
+/// distances the middle one is returned, leaving the widest margin on both sides. Both sets of pairs should be non empty.
+pub fn calibrate(positive_pairs : &[(Image, Image)], negative_pairs : &[(Image, Image)], config : &Config) -> Result<u8> {
+    if positive_pairs.is_empty() || negative_pairs.is_empty() {
+        return Err(ImgcmpError::InvalidConfig("calibrating needs both positive and negative pairs of images".to_string()));
+    }
+
+    let hasher = PerceptualHasher::new(config.clone());
+    let distances = |pairs : &[(Image, Image)]| pairs.iter().
+        map(|(left, right)| Ok(hasher.hash(left)?.distance(&hasher.hash(right)?))).
+        collect::<Result<Vec<u32>>>();
+    let positive_distances = distances(positive_pairs)?;
+    let negative_distances = distances(negative_pairs)?;
+
+    let max_distance = config.hash_bit_count().min(u8::MAX as u32);
+    let accuracy = |allowed_distance : u32| positive_distances.iter().filter(|distance| **distance <= allowed_distance).count() +
+        negative_distances.iter().filter(|distance| **distance > allowed_distance).count();
+    let best_accuracy = (0..=max_distance).map(accuracy).max().unwrap_or(0);
+    let first_best = (0..=max_distance).find(|distance| accuracy(*distance) == best_accuracy).unwrap_or(0);
+    let last_best = (first_best..=max_distance).take_while(|distance| accuracy(*distance) == best_accuracy).last().unwrap_or(first_best);
+    log::debug!("Calibrated allowed distance to {}..={}, classifying {} of {} pairs correctly", first_best, last_best,
+                best_accuracy, positive_distances.len() + negative_distances.len());
+    Ok(((first_best + last_best) / 2) as u8)
+}
+
+#[cfg(feature = "std")]
+/// Compares two images passed as raw RGBA buffers of the same dimensions, e.g. as provided by
+/// a `<canvas>`. No file I/O is involved, so this can be used in WebAssembly.
+pub fn compare_rgba(left : &[u8], right : &[u8], width : u32, height : u32, config : &Config) -> Result<bool> {
+    let expected_len = width as usize * height as usize * 4;
+    if left.len() != expected_len || right.len() != expected_len {
+        let actual = if left.len() != expected_len { left.len() } else { right.len() };
+        return Err(ImgcmpError::BufferLengthMismatch { expected : expected_len, actual });
+    }
+
+    let left_image = Image::from(left, width, 4)?;
+    let right_image = Image::from(right, width, 4)?;
+    compare_images(&left_image, &right_image, config.clone())
+}
+
+#[cfg(feature = "std")]
+/// Hashes the passed image and returns the hash along with the intermediate values used to create it.
+pub fn hash_image_details(image : &Image, config : &Config) -> Result<HashDetails> {
+    PerceptualHasher::new(config.clone()).hash_details(image)
+}
+
+#[cfg(feature = "std")]
+/// Hashes the passed image, ignoring the pixels flagged in `mask`. See `PerceptualHasher::hash_masked`.
+pub fn hash_image_masked(image : &Image, mask : Option<&[bool]>, config : &Config) -> Result<ImageHash> {
+    PerceptualHasher::new(config.clone()).hash_masked(image, mask)
+}
+
+#[cfg(feature = "std")]
+/// Hashes an image that is already scaled down to `dct_dimension`x`dct_dimension` and converted to
+/// grayscale, e.g. by a GPU pipeline, skipping both steps.
+pub fn hash_preprocessed(gray_square : &Image, config : &Config) -> Result<ImageHash> {
+    PerceptualHasher::new(config.clone()).hash_preprocessed(gray_square)
+}
+
+#[cfg(feature = "std")]
+/// Mean squared error between the channels of two images of the same dimensions and channels.
+/// Unlike the perceptual hash, it measures exact pixel fidelity.
+pub fn mse(left : &Image, right : &Image) -> Result<f64> {
+    let difference = left.difference(right)?.to_bytes();
+    let squared_sum : f64 = difference.iter().map(|d| (*d as f64).powi(2)).sum();
+    Ok(squared_sum / difference.len() as f64)
+}
+
+#[cfg(feature = "std")]
+/// Peak signal-to-noise ratio in decibels between two images of the same dimensions and channels.
+/// Identical images have an infinite PSNR.
+pub fn psnr(left : &Image, right : &Image) -> Result<f64> {
+    let mse = mse(left, right)?;
+    let max = u8::MAX as f64;
+    Ok(10.0 * (max * max / mse).log10())
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use anyhow::Context;
@@ -58,19 +622,19 @@ mod tests {
 
     #[test]
     fn identical_images_are_same() -> anyhow::Result<()> {
-        let img1 = read_image("../assets/cat.jpg").and_then(|x| to_image(x))?;
-        let img2 = read_image("../assets/cat.jpg").and_then(|x| to_image(x))?;
+        let img1 = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/cat.jpg").and_then(to_image)?;
 
-        assert_eq!(compare_images(&img1, &img2, test_config())?, true);
+        assert!(compare_images(&img1, &img2, test_config())?);
         Ok(())
     }
 
     #[test]
     fn different_images_are_not_same() -> anyhow::Result<()> {
-        let img1 = read_image("../assets/cat.jpg").and_then(|x| to_image(x))?;
-        let img2 = read_image("../assets/cat2.jpg").and_then(|x| to_image(x))?;
+        let img1 = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/cat2.jpg").and_then(to_image)?;
 
-        assert_eq!(compare_images(&img1, &img2, test_config())?, false);
+        assert!(!compare_images(&img1, &img2, test_config())?);
         Ok(())
     }
 
@@ -79,8 +643,8 @@ mod tests {
         let img = read_image("../assets/cat.jpg")?;
         let grayscale_img = img.grayscale();
 
-        assert_eq!(compare_images(&to_image(img)?,
-                                  &to_image(grayscale_img)?, test_config())?, true);
+        assert!(compare_images(&to_image(img)?,
+                               &to_image(grayscale_img)?, test_config())?);
         Ok(())
     }
 
@@ -89,8 +653,8 @@ mod tests {
         let img = read_image("../assets/cat.jpg")?;
         let blurred_img = img.blur(3.0);
 
-        assert_eq!(compare_images(&to_image(img)?,
-                                  &to_image(blurred_img)?, test_config())?, true);
+        assert!(compare_images(&to_image(img)?,
+                               &to_image(blurred_img)?, test_config())?);
         Ok(())
     }
 
@@ -99,8 +663,8 @@ mod tests {
         let img = read_image("../assets/cat.jpg")?;
         let distorted_img = img.resize_exact(img.width() / 4, img.height() / 2, FilterType::Gaussian);
 
-        assert_eq!(compare_images(&to_image(img)?,
-                                  &to_image(distorted_img)?, test_config())?, true);
+        assert!(compare_images(&to_image(img)?,
+                               &to_image(distorted_img)?, test_config())?);
         Ok(())
     }
 
@@ -111,8 +675,8 @@ mod tests {
             resize_exact(img.width() / 10, img.height() / 2, FilterType::Gaussian).
             blur(3.0);
 
-        assert_eq!(compare_images(&to_image(img)?,
-                                  &to_image(blurred_img)?, test_config())?, true);
+        assert!(compare_images(&to_image(img)?,
+                               &to_image(blurred_img)?, test_config())?);
         Ok(())
     }
 
@@ -122,8 +686,8 @@ mod tests {
         let blurred_img1 = img.blur(3.0);
         let blurred_img2 = img.blur(0.5);
 
-        assert_eq!(compare_images(&to_image(blurred_img1)?,
-                                  &to_image(blurred_img2)?, test_config())?, true);
+        assert!(compare_images(&to_image(blurred_img1)?,
+                               &to_image(blurred_img2)?, test_config())?);
         Ok(())
     }
 
@@ -133,8 +697,8 @@ mod tests {
         let resized_img1 = img.resize_exact(img.width() / 4, img.height() / 2, FilterType::Lanczos3);
         let resized_img2 = img.resize_exact(img.width() / 2, img.height() / 4, FilterType::CatmullRom);
 
-        assert_eq!(compare_images(&to_image(resized_img1)?,
-                                  &to_image(resized_img2)?, test_config())?, true);
+        assert!(compare_images(&to_image(resized_img1)?,
+                               &to_image(resized_img2)?, test_config())?);
         Ok(())
     }
 
@@ -148,23 +712,1109 @@ mod tests {
             resize_exact(img.width() / 10, img.height() / 2, FilterType::Gaussian).
             blur(0.5);
 
-        assert_eq!(compare_images(&to_image(blurred_img1)?,
-                                  &to_image(blurred_img2)?, test_config())?, true);
+        assert!(compare_images(&to_image(blurred_img1)?,
+                               &to_image(blurred_img2)?, test_config())?);
         Ok(())
     }
 
     #[test]
     fn different_shrank_and_blurred_images_are_not_same() -> anyhow::Result<()> {
-        let img1 = read_image("../assets/cat.jpg").
-            and_then(|x| Ok(x.resize_exact(32, 32, FilterType::Gaussian))).
-            and_then(|x| Ok(x.blur(0.5))).
-            and_then(|x| to_image(x))?;
-        let img2 = read_image("../assets/cat2.jpg").
-            and_then(|x| Ok(x.resize_exact(32, 32, FilterType::Gaussian))).
-            and_then(|x| Ok(x.blur(0.5))).
-            and_then(|x| to_image(x))?;
+        let img1 = read_image("../assets/cat.jpg").map(|x| x.resize_exact(32, 32, FilterType::Gaussian)).map(|x| x.blur(0.5)).
+            and_then(to_image)?;
+        let img2 = read_image("../assets/cat2.jpg").map(|x| x.resize_exact(32, 32, FilterType::Gaussian)).map(|x| x.blur(0.5)).
+            and_then(to_image)?;
+
+        assert!(!compare_images(&img1, &img2, test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_details_of_identical_images_are_equal() -> anyhow::Result<()> {
+        let img1 = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        let details1 = hash_image_details(&img1, &test_config())?;
+        let details2 = hash_image_details(&img2, &test_config())?;
+
+        assert_eq!(details1, details2);
+        assert!(details1.threshold.is_finite());
+        Ok(())
+    }
+
+    #[test]
+    fn center_cropped_image_is_same_with_original_only_in_multiscale_mode() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg")?;
+        let cropped_img = img.crop_imm(img.width() / 4, img.height() / 4, img.width() / 2, img.height() / 2);
+        let (img, cropped_img) = (to_image(img)?, to_image(cropped_img)?);
+
+        assert!(!compare_images(&img, &cropped_img, test_config())?);
+        assert!(compare_images_multiscale(&img, &cropped_img, test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn different_images_are_not_same_in_multiscale_mode() -> anyhow::Result<()> {
+        let img1 = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/cat2.jpg").and_then(to_image)?;
+
+        assert!(!compare_images_multiscale(&img1, &img2, test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_images_smaller_than_grid_in_multiscale_mode() -> anyhow::Result<()> {
+        let tiny_img = Image::from_luma(&[10, 200, 30, 140, 50, 160], 3)?;
+        let config = Config { multiscale_grid : 4, ..test_config() };
+
+        assert!(compare_images_multiscale(&tiny_img, &tiny_img.clone(), config)?);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_image_with_rectangular_reduced_region() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let wide_config = Config { dct_reduced_shape : Some((16, 4)), ..test_config() };
+        let too_large_config = Config { dct_reduced_shape : Some((16, 8)), ..test_config() };
+
+        assert!(compare_images(&img, &img.clone(), wide_config)?);
+        assert_eq!(hash_image_details(&img, &too_large_config),
+                   Err(ImgcmpError::HashTooLarge { bits : 128, max_bits : 64 }));
+        Ok(())
+    }
+
+    #[test]
+    fn return_best_matching_reference() -> anyhow::Result<()> {
+        let query = read_image("../assets/cat.jpg")?.blur(1.0);
+        let refs = vec!(
+            read_image("../assets/ferrari_roma.jpg").and_then(to_image)?,
+            read_image("../assets/cat.jpg").and_then(to_image)?,
+            read_image("../assets/cat2.jpg").and_then(to_image)?);
+
+        let config = Config { allowed_distance : 3, ..test_config() };
+        let result = best_match(&to_image(query)?, &refs, &config)?;
+
+        assert!(matches!(result, Some((1, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn classify_hashes_by_distance() {
+        let hasher = PerceptualHasher::new(Config { allowed_distance : 3, ..test_config() });
+        let hash = ImageHash(0b1111_0000);
+
+        assert_eq!(hasher.classify(&hash, &ImageHash(0b1111_0000)), MatchClass::Identical);
+        assert_eq!(hasher.classify(&hash, &ImageHash(0b1111_1110)), MatchClass::NearDuplicate);
+        assert_eq!(hasher.classify(&hash, &ImageHash(0b0011_1111)), MatchClass::Similar);
+        assert_eq!(hasher.classify(&hash, &ImageHash(0b0000_1111)), MatchClass::Different);
+    }
+
+    #[test]
+    fn break_distance_down_by_frequency_band() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+        // Bits of the (1, 0), (3, 3), (6, 7) and (7, 7) frequencies of the 8x8 block
+        let differing_bits = (1 << 1) | (1 << 27) | (1 << 62) | (1 << 63);
+
+        let breakdown = hasher.distance_breakdown(&ImageHash(0), &ImageHash(differing_bits));
+
+        assert_eq!(breakdown, DistanceBreakdown { low_frequency : 1, mid_frequency : 1, high_frequency : 2 });
+        assert_eq!(breakdown.total(), 4);
+
+        let img = read_image("../assets/ferrari_roma.jpg").and_then(to_image)?;
+        let edited_img = read_image("../assets/ferrari_roma_edited.png").and_then(to_image)?;
+        assert_eq!(compare_images_breakdown(&img, &edited_img, &test_config())?.total(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn classify_images() -> anyhow::Result<()> {
+        let img = read_image("../assets/ferrari_roma.jpg").and_then(to_image)?;
+        let edited_img = read_image("../assets/ferrari_roma_edited.png").and_then(to_image)?;
+        let different_img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        assert_eq!(classify(&img, &img.clone(), &test_config())?, MatchClass::Identical);
+        assert_eq!(classify(&img, &edited_img, &Config { allowed_distance : 2, ..test_config() })?, MatchClass::NearDuplicate);
+        assert_eq!(classify(&img, &edited_img, &Config { allowed_distance : 1, ..test_config() })?, MatchClass::Similar);
+        assert_eq!(classify(&img, &different_img, &Config::default())?, MatchClass::Different);
+        Ok(())
+    }
+
+    #[test]
+    fn cluster_near_duplicate_images() -> anyhow::Result<()> {
+        let images = ["cat.jpg", "ferrari_roma.jpg", "cat2.jpg", "cat_edited.jpg", "ferrari_roma_edited.png"].iter().
+            map(|name| read_image(&format!("../assets/{}", name)).and_then(to_image)).
+            collect::<anyhow::Result<Vec<Image>>>()?;
+
+        let clusters = cluster_duplicates(&images, &Config::default())?;
+
+        assert_eq!(clusters, vec!(vec!(0, 3), vec!(1, 4), vec!(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn return_no_match_when_no_reference_is_within_allowed_distance() -> anyhow::Result<()> {
+        let query = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let refs = vec!(
+            read_image("../assets/ferrari_roma.jpg").and_then(to_image)?,
+            read_image("../assets/cat2.jpg").and_then(to_image)?);
+
+        assert_eq!(best_match(&query, &refs, &test_config())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn blurring_reduces_distance_of_noisy_images() -> anyhow::Result<()> {
+        // Image is only slightly bigger than the DCT, so noise is not averaged out while scaling
+        let img = read_image("../assets/cat.jpg")?.resize_exact(48, 48, FilterType::Gaussian);
+        let noisy_imgs = (0..10).map(|i| {
+            let mut noisy_img = img.to_rgb8();
+            let mut seed = i * 7919 + 1_u32;
+            for channel in noisy_img.iter_mut() {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let noise = ((seed >> 16) % 201) as i32 - 100;
+                *channel = (*channel as i32 + noise).clamp(0, 255) as u8;
+            }
+            to_image(DynamicImage::ImageRgb8(noisy_img))
+        }).collect::<anyhow::Result<Vec<Image>>>()?;
+        let img = to_image(img)?;
+
+        let total_distance = |config : Config| -> anyhow::Result<u32> {
+            let hasher = PerceptualHasher::new(config);
+            let hash = hasher.hash(&img)?;
+            noisy_imgs.iter().try_fold(0, |total, noisy_img| Ok(total + hash.distance(&hasher.hash(noisy_img)?)))
+        };
+        let distance_without_blur = total_distance(test_config())?;
+        let distance_with_blur = total_distance(Config { blur_sigma : Some(1.0), ..test_config() })?;
+
+        assert!(distance_with_blur < distance_without_blur,
+                "{} >= {}", distance_with_blur, distance_without_blur);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_solid_color_images_to_all_zeros() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+
+        for value in [0, 1, 77, 128, 255] {
+            let image = Image::from_luma(&[value; 64 * 48], 64)?;
+            assert_eq!(hasher.hash(&image)?, ImageHash(0), "Unexpected hash of solid image {}", value);
+        }
+
+        let image = Image::from_luma(&[128; 64 * 48], 64)?;
+        let above_or_equal_hasher = PerceptualHasher::new(Config { tie_breaking : TieBreaking::AboveOrEqual, ..test_config() });
+        assert_eq!(above_or_equal_hasher.hash(&image)?, ImageHash(u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_preprocessed_luma_image() -> anyhow::Result<()> {
+        let luma_pixels : Vec<u8> = (0..32 * 32).map(|i| ((i % 32) * 8 + (i / 32) * 3) as u8).collect();
+        let luma_image = Image::from_luma(&luma_pixels, 32)?;
+
+        let hash = hash_preprocessed(&luma_image, &test_config())?;
+
+        assert_eq!(hash, hash_image_details(&luma_image, &test_config())?.hash);
+        assert_ne!(hash, ImageHash(0));
+        Ok(())
+    }
+
+    #[test]
+    fn do_not_hash_preprocessed_image_of_wrong_shape() -> anyhow::Result<()> {
+        let small_image = Image::from_luma(&[0; 16 * 16], 16)?;
+        let color_image = Image::from(&[0; 32 * 32 * 3], 32, 3)?;
+
+        assert!(matches!(hash_preprocessed(&small_image, &test_config()), Err(ImgcmpError::InvalidDimensions(_))));
+        assert!(matches!(hash_preprocessed(&color_image, &test_config()), Err(ImgcmpError::InvalidDimensions(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn create_preset_configs() {
+        assert_eq!(Config::strict().allowed_distance, 0);
+        assert_eq!(Config::lenient().allowed_distance, 10);
+        assert_eq!(Config::fast().dct_dimension, 16);
+        assert_eq!(Config::fast().dct_reduced_dimension, 8);
+        assert_eq!(Config::fast().allowed_distance, Config::default().allowed_distance);
+        assert_eq!(Config::fast_preview().dct_dimension, 16);
+        assert_eq!(Config::fast_preview().downscale_mode, ScaleMode::Point);
+    }
+
+    #[test]
+    fn staged_comparison_agrees_with_full_comparison() -> anyhow::Result<()> {
+        let paths = ["../assets/cat.jpg", "../assets/cat_edited.jpg", "../assets/cat2.jpg", "../assets/cat2_edited.jpg"];
+        let images = paths.iter().map(|path| read_image(path).and_then(to_image)).collect::<anyhow::Result<Vec<Image>>>()?;
+        let preview_hasher = PerceptualHasher::new(Config::fast_preview());
+
+        for (i, left_image) in images.iter().enumerate() {
+            for right_image in &images[i + 1..] {
+                assert_eq!(compare_images_staged(left_image, right_image, Config::default())?,
+                           compare_images(left_image, right_image, Config::default())?);
+            }
+        }
+        assert!(!preview_hasher.are_same(&preview_hasher.hash(&images[0])?, &preview_hasher.hash(&images[2])?));
+        Ok(())
+    }
+
+    #[test]
+    fn staged_comparison_accepts_pairs_accepted_by_full_comparison() -> anyhow::Result<()> {
+        let paths = ["../assets/cat.jpg", "../assets/cat_edited.jpg", "../assets/cat2.jpg", "../assets/cat2_edited.jpg",
+                     "../assets/cat_exif_upright.jpg"];
+        let images = paths.iter().map(|path| read_image(path).and_then(to_image)).collect::<anyhow::Result<Vec<Image>>>()?;
+        let configs = [
+            Config { allowed_distance : 20, ..Config::default() },
+            Config { distance_metric : DistanceMetric::NormalizedHamming { max_distance : 0.4 }, ..Config::default() },
+            Config { threshold : ThresholdStrategy::Median, allowed_distance : 16, ..Config::default() },
+            Config { dct_dimensions : vec!(16, 32), allowed_distance : 14, ..Config::default() },
+            Config { dct_reduced_dimension : 4, allowed_distance : 5, ..Config::fast() }
+        ];
+
+        let mut accepted_pairs = 0;
+        for config in &configs {
+            for (i, left_image) in images.iter().enumerate() {
+                for right_image in &images[i + 1..] {
+                    let is_accepted = compare_images(left_image, right_image, config.clone())?;
+                    assert_eq!(compare_images_staged(left_image, right_image, config.clone())?, is_accepted, "{:?}", config);
+                    accepted_pairs += is_accepted as u32;
+                }
+            }
+        }
+        assert!(accepted_pairs >= configs.len() as u32 * 2, "{}", accepted_pairs);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_images_hashed_at_multiple_dct_dimensions() -> anyhow::Result<()> {
+        let image = |path : &str| read_image(path).and_then(to_image);
+        let (cat, cat_edited, cat2) = (image("../assets/cat.jpg")?, image("../assets/cat_edited.jpg")?, image("../assets/cat2.jpg")?);
+        let multi_dimension_config = Config { dct_dimensions : vec!(16, 32), ..Config::default() };
+
+        for other in [&cat_edited, &cat2] {
+            let single_dimension_verdict = compare_images(&cat, other, Config::default())?;
+            assert_eq!(compare_images(&cat, other, multi_dimension_config.clone())?, single_dimension_verdict);
+        }
+        assert!(compare_images(&cat, &cat_edited, multi_dimension_config.clone())?);
+        assert!(!compare_images(&cat, &cat2, multi_dimension_config.clone())?);
+
+        let hashers = [16, 32].map(|dct_dimension| PerceptualHasher::new(Config { dct_dimension, ..Config::default() }));
+        let combined_distance = |left : &Image, right : &Image| -> anyhow::Result<f32> {
+            Ok(hashers.iter().map(|hasher| Ok(hasher.distance(&hasher.hash(left)?, &hasher.hash(right)?))).sum::<Result<f32>>()?)
+        };
+        let tight_config = Config { allowed_distance : (combined_distance(&cat, &cat2)? / 2.0).ceil() as u8, ..multi_dimension_config };
+        assert!(compare_images(&cat, &cat2, tight_config.clone())?);
+        assert!(!compare_images(&cat, &cat2, Config { allowed_distance : tight_config.allowed_distance - 1, ..tight_config })?);
+        Ok(())
+    }
+
+    #[test]
+    fn calibrate_allowed_distance_separating_pairs() -> anyhow::Result<()> {
+        let image = |path : &str| read_image(path).and_then(to_image);
+        let (cat, cat_edited) = (image("../assets/cat.jpg")?, image("../assets/cat_edited.jpg")?);
+        let (cat2, cat2_edited) = (image("../assets/cat2.jpg")?, image("../assets/cat2_edited.jpg")?);
+        let ferrari = image("../assets/ferrari_roma.jpg")?;
+        let hasher = PerceptualHasher::new(Config::default());
+        let distance = |left : &Image, right : &Image| -> anyhow::Result<u32> { Ok(hasher.hash(left)?.distance(&hasher.hash(right)?)) };
+
+        let positive_pairs = vec!((cat.clone(), cat_edited.clone()), (cat2.clone(), cat2_edited));
+        let negative_pairs = vec!((cat.clone(), cat2.clone()), (cat_edited, ferrari));
+        let allowed_distance = calibrate(&positive_pairs, &negative_pairs, &Config::default())?;
+
+        for (left, right) in &positive_pairs {
+            assert!(distance(left, right)? <= allowed_distance as u32);
+        }
+        for (left, right) in &negative_pairs {
+            assert!(distance(left, right)? > allowed_distance as u32);
+        }
+        assert!(compare_images(&cat, &positive_pairs[0].1, Config { allowed_distance, ..Config::default() })?);
+        assert!(!compare_images(&cat, &cat2, Config { allowed_distance, ..Config::default() })?);
+        assert!(matches!(calibrate(&positive_pairs, &[], &Config::default()), Err(ImgcmpError::InvalidConfig(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn derive_allowed_distance_from_similarity_threshold() {
+        let config = Config::default().with_similarity_threshold(0.05);
+        let wide_config = Config { dct_reduced_dimension : 16, ..Config::default() }.with_similarity_threshold(0.05);
+
+        assert_eq!(config.hash_bit_count(), 64);
+        assert_eq!(config.allowed_distance, 3);
+        assert_eq!(wide_config.hash_bit_count(), 256);
+        assert_eq!(wide_config.allowed_distance, 13);
+        assert_eq!(Config::default().with_similarity_threshold(0.0).allowed_distance, 0);
+    }
+
+    #[test]
+    fn weight_hash_bits_by_their_frequency() {
+        let weights = Config { coefficient_order : CoefficientOrder::ZigZag, ..Config::default() }.bit_weights();
+
+        assert_eq!(weights.len(), 64);
+        assert_eq!(&weights[..3], &[1.0, 1.0, 1.0]);
+        assert_eq!(weights[63], 1.0 / 14.0);
+    }
+
+    #[test]
+    fn reject_allowed_distance_exceeding_hash_bits() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let config = Config { allowed_distance : 100, dct_reduced_dimension : 8, ..Config::default() };
+
+        assert!(matches!(config.validate(), Err(ImgcmpError::InvalidConfig(_))));
+        assert!(matches!(compare_images(&img, &img, config), Err(ImgcmpError::InvalidConfig(_))));
+        assert_eq!(Config { allowed_distance : 64, ..Config::default() }.validate(), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_configurations_that_cannot_hash_images() {
+        let invalid_configs = [
+            Config { hash_bits : Some(65), ..Config::default() },
+            Config { hash_bits : Some(0), ..Config::default() },
+            Config { dct_dimension : 0, ..Config::default() },
+            Config { dct_dimensions : vec!(16, 0), ..Config::default() }
+        ];
+
+        for config in &invalid_configs {
+            assert!(matches!(config.validate(), Err(ImgcmpError::InvalidConfig(_))), "{:?}", config);
+            assert!(matches!(PerceptualHasher::try_new(config.clone()), Err(ImgcmpError::InvalidConfig(_))), "{:?}", config);
+        }
+        assert_eq!(Config { hash_bits : Some(64), dct_dimensions : vec!(16, 32), ..Config::default() }.validate(), Ok(()));
+        assert!(PerceptualHasher::try_new(Config::default()).is_ok());
+    }
+
+    #[test]
+    fn hash_ignores_horizontal_gradient_when_first_row_and_column_are_excluded() -> anyhow::Result<()> {
+        let pixels : Vec<u8> = (0..32 * 32).map(|i| (60 + (i % 32) * (i / 32) % 120) as u8).collect();
+        let gradient_pixels : Vec<u8> = pixels.iter().enumerate().map(|(i, pixel)| pixel + (i % 32) as u8 * 2).collect();
+        let (image, gradient_image) = (Image::from_luma(&pixels, 32)?, Image::from_luma(&gradient_pixels, 32)?);
+        let config = Config { exclude_first_row_col : true, ..test_config() };
+
+        assert_ne!(hash_image_details(&image, &test_config())?.hash, hash_image_details(&gradient_image, &test_config())?.hash);
+        assert_eq!(hash_image_details(&image, &config)?.hash, hash_image_details(&gradient_image, &config)?.hash);
+        assert_eq!(config.hash_bit_count(), 49);
+        assert_eq!(Config { hash_bits : Some(16), ..config }.hash_bit_count(), 16);
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_preset_accepts_more_images_than_strict() -> anyhow::Result<()> {
+        let cat = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let cat2 = read_image("../assets/cat2.jpg").and_then(to_image)?;
+        let ferrari = read_image("../assets/ferrari_roma.jpg").and_then(to_image)?;
+        let ferrari_edited = read_image("../assets/ferrari_roma_edited.png").and_then(to_image)?;
 
-        assert_eq!(compare_images(&img1, &img2, test_config())?, false);
+        assert!(!compare_images(&cat, &cat2, Config::strict())?);
+        assert!(!compare_images(&ferrari, &ferrari_edited, Config::strict())?);
+        assert!(!compare_images(&cat, &cat2, Config::lenient())?);
+        assert!(compare_images(&ferrari, &ferrari_edited, Config::lenient())?);
+        Ok(())
+    }
+
+    #[test]
+    fn identical_images_have_zero_mse() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        assert_eq!(mse(&img, &img.clone())?, 0.0);
+        assert_eq!(psnr(&img, &img.clone())?, f64::INFINITY);
+        Ok(())
+    }
+
+    #[test]
+    fn return_mse_of_single_pixel_change() -> anyhow::Result<()> {
+        let pixels = vec!(100; 10 * 10 * 3);
+        let mut changed_pixels = pixels.clone();
+        changed_pixels[0] = 110;
+        let image = Image::from(&pixels, 10, 3)?;
+        let changed_image = Image::from(&changed_pixels, 10, 3)?;
+
+        // A single channel differs by 10, over 300 channels
+        assert!((mse(&image, &changed_image)? - 100.0 / 300.0).abs() < 1e-9);
+        assert!((psnr(&image, &changed_image)? - 10.0 * (255.0_f64 * 255.0 * 3.0).log10()).abs() < 1e-9);
+        assert!(matches!(mse(&image, &Image::from(&pixels[..100], 10, 1)?), Err(ImgcmpError::ChannelCountMismatch { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_image_with_requested_number_of_bits() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let config = Config { hash_bits : Some(40), ..test_config() };
+
+        let hash = hash_image_details(&img, &config)?.hash;
+
+        assert_eq!(hash.0 >> 40, 0);
+        assert_ne!(hash, ImageHash(0));
+        assert_eq!(config.coefficient_positions().len(), 40);
+        assert!(config.coefficient_positions().iter().all(|(horizontal, vertical)| horizontal + vertical <= 8));
+        assert_eq!(hash_image_details(&img, &Config { hash_bits : Some(65), ..test_config() }),
+                   Err(ImgcmpError::HashTooLarge { bits : 65, max_bits : 64 }));
+        Ok(())
+    }
+
+    #[test]
+    fn zigzag_order_permutes_bits_of_hash() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let zigzag_config = Config { coefficient_order : CoefficientOrder::ZigZag, ..test_config() };
+
+        let hash = hash_image_details(&img, &test_config())?.hash;
+        let zigzag_hash = hash_image_details(&img, &zigzag_config)?.hash;
+
+        let permuted_hash = core::zigzag_positions(8, 8).iter().enumerate().
+            fold(0_u64, |permuted, (bit, (x, y))| permuted | (((hash.0 >> (y * 8 + x)) & 1) << bit));
+        assert_eq!(zigzag_hash, ImageHash(permuted_hash));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_image_through_comparator_trait_object() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let hasher = PerceptualHasher::new(test_config());
+        let comparator : &dyn Comparator = &hasher;
+
+        assert_eq!(comparator.hash(&img)?, hasher.hash(&img)?);
+        assert!(compare_images_with(&img, &img.clone(), comparator)?);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_images_with_custom_comparator() -> anyhow::Result<()> {
+        struct BrightnessComparator;
+        impl Comparator for BrightnessComparator {
+            fn hash(&self, image : &Image) -> Result<ImageHash> {
+                Ok(ImageHash(image.mean_brightness() as u64 / 16))
+            }
+            fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool {
+                left_hash == right_hash
+            }
+        }
+        let dark_image = Image::from_luma(&[10; 16], 4)?;
+        let darker_image = Image::from_luma(&[12; 16], 4)?;
+        let bright_image = Image::from_luma(&[200; 16], 4)?;
+
+        assert!(compare_images_with(&dark_image, &darker_image, &BrightnessComparator)?);
+        assert!(!compare_images_with(&dark_image, &bright_image, &BrightnessComparator)?);
+        Ok(())
+    }
+
+    /// Records the messages logged by each thread, so that tests running in parallel see only their own messages
+    struct CapturingLogger;
+
+    thread_local! {
+        static LOGGED_MESSAGES : std::cell::RefCell<Vec<(log::Level, String)>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata : &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record : &log::Record<'_>) {
+            LOGGED_MESSAGES.with(|messages| messages.borrow_mut().push((record.level(), record.args().to_string())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_logs<T, F : FnOnce() -> T>(f : F) -> (T, Vec<(log::Level, String)>) {
+        static LOGGER : CapturingLogger = CapturingLogger;
+        static INSTALL_LOGGER : std::sync::Once = std::sync::Once::new();
+        INSTALL_LOGGER.call_once(|| {
+            log::set_logger(&LOGGER).expect("no other logger is installed by tests");
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+
+        LOGGED_MESSAGES.with(|messages| messages.borrow_mut().clear());
+        let result = f();
+        (result, LOGGED_MESSAGES.with(|messages| messages.borrow_mut().split_off(0)))
+    }
+
+    #[test]
+    fn log_pipeline_stages() -> anyhow::Result<()> {
+        let img1 = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/cat2.jpg").and_then(to_image)?;
+
+        let (are_same, messages) = capture_logs(|| compare_images(&img1, &img2, test_config()));
+
+        assert!(!are_same?);
+        let logged = |level : log::Level, prefix : &str| messages.iter().
+            filter(|(message_level, message)| *message_level == level && message.starts_with(prefix)).
+            count();
+        assert_eq!(logged(log::Level::Debug, &format!("Hashing {}x{} image of 3 channels, scaling it to 32x32",
+                                                       img1.get_width(), img1.get_height())), 1);
+        assert_eq!(logged(log::Level::Trace, "Converted image to grayscale using Average mode"), 2);
+        assert_eq!(logged(log::Level::Trace, "Threshold of DCT coefficients is "), 2);
+        assert_eq!(logged(log::Level::Debug, "Computed hash 0x"), 2);
+        assert_eq!(logged(log::Level::Debug, "Hashes 0x"), 1);
+        assert!(messages.last().is_some_and(|(_, message)| message.ends_with("are at distance 31, images are different")),
+                "{:?}", messages.last());
+        Ok(())
+    }
+
+    #[test]
+    fn compare_identical_images_without_hashing() -> anyhow::Result<()> {
+        struct CountingComparator {
+            hash_calls : std::cell::Cell<u32>
+        }
+        impl Comparator for CountingComparator {
+            fn hash(&self, image : &Image) -> Result<ImageHash> {
+                self.hash_calls.set(self.hash_calls.get() + 1);
+                Ok(ImageHash(image.mean_brightness() as u64))
+            }
+            fn are_same(&self, _left_hash : &ImageHash, _right_hash : &ImageHash) -> bool {
+                false
+            }
+        }
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let comparator = CountingComparator { hash_calls : std::cell::Cell::new(0) };
+
+        assert!(compare_images_with(&img, &img.clone(), &comparator)?);
+        assert_eq!(comparator.hash_calls.get(), 0);
+        assert!(compare_images(&img, &img.clone(), test_config())?);
+
+        assert!(!compare_images_with(&img, &img.grayscale(), &comparator)?);
+        assert_eq!(comparator.hash_calls.get(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn import_common_items_from_prelude() -> anyhow::Result<()> {
+        use crate::prelude::*;
+
+        let config = Config { allowed_distance : 5, ..Config::default() };
+        let image = Image::from_fn(64, 64, 3, |x, y| vec!(x as u8 * 4, y as u8 * 4, 128));
+        let hasher = PerceptualHasher::new(config.clone());
+        let hash : ImageHash = Comparator::hash(&hasher, &image)?;
+
+        assert!(compare_images(&image, &image.grayscale(), config)?);
+        assert_eq!(hash, hasher.hash(&image.grayscale())?);
+        assert!(matches!(Image::from(&[], 1, 1), Err(ImgcmpError::InvalidDimensions(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn inverted_image_is_different() -> anyhow::Result<()> {
+        let image = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let inverted_image = image.invert();
+        let hasher = PerceptualHasher::new(Config::default());
+
+        let distance = hasher.hash(&image)?.distance(&hasher.hash(&inverted_image)?);
+
+        assert!(distance > Config::default().allowed_distance as u32);
+        assert!(!compare_images(&image, &inverted_image, Config::default())?);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_repeated_query_once() -> anyhow::Result<()> {
+        struct CountingComparator {
+            hash_calls : std::cell::Cell<u32>,
+            hasher : PerceptualHasher
+        }
+        impl Comparator for CountingComparator {
+            fn hash(&self, image : &Image) -> Result<ImageHash> {
+                self.hash_calls.set(self.hash_calls.get() + 1);
+                self.hasher.hash(image)
+            }
+            fn are_same(&self, left_hash : &ImageHash, right_hash : &ImageHash) -> bool {
+                self.hasher.are_same(left_hash, right_hash)
+            }
+        }
+        let counting_comparator = || CountingComparator { hash_calls : std::cell::Cell::new(0), hasher : PerceptualHasher::new(Config::default()) };
+        let paths = ["../assets/cat.jpg", "../assets/cat_edited.jpg", "../assets/cat2.jpg", "../assets/ferrari_roma.jpg"];
+        let images = paths.iter().map(|path| read_image(path).and_then(to_image)).collect::<anyhow::Result<Vec<Image>>>()?;
+        let (query, refs) = (&images[0], &images[1..]);
+
+        let comparator = CachingComparator::new(counting_comparator(), 4);
+        let mut verdicts = Vec::new();
+        for _ in 0..3 {
+            for reference in refs {
+                verdicts.push(compare_images_with(query, reference, &comparator)?);
+            }
+        }
+        assert_eq!(comparator.get_comparator().hash_calls.get(), 4);
+        assert_eq!(verdicts, [true, false, false].repeat(3));
+
+        let forgetful_comparator = CachingComparator::new(counting_comparator(), 1);
+        for reference in refs {
+            compare_images_with(query, reference, &forgetful_comparator)?;
+        }
+        assert_eq!(forgetful_comparator.get_comparator().hash_calls.get(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn locate_altered_tile_by_its_hash() -> anyhow::Result<()> {
+        let pattern = |x : u32, y : u32| ((x * 7 + y * 13 + x * y) % 256) as u8;
+        let pixels : Vec<u8> = (0..64 * 64).map(|i| pattern(i % 64, i / 64)).collect();
+        // Invert the top right quadrant
+        let altered_pixels : Vec<u8> = pixels.iter().enumerate().
+            map(|(i, p)| if i % 64 >= 32 && i / 64 < 32 { 255 - p } else { *p }).
+            collect();
+        let image = Image::from_luma(&pixels, 64)?;
+        let altered_image = Image::from_luma(&altered_pixels, 64)?;
+
+        let hashes = hash_tiles(&image, (2, 2), &test_config())?;
+        let altered_hashes = hash_tiles(&altered_image, (2, 2), &test_config())?;
+
+        assert_eq!(hashes.len(), 4);
+        assert_eq!(altered_hashes.len(), 4);
+        let differing_tiles : Vec<usize> = (0..4).filter(|i| hashes[*i] != altered_hashes[*i]).collect();
+        assert_eq!(differing_tiles, [1]);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_view_like_cropped_copy() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let blur_config = Config { blur_sigma : Some(1.0), ..test_config() };
+
+        for config in [test_config(), Config::fast(), blur_config] {
+            let hasher = PerceptualHasher::new(config);
+            let view = img.view(40, 30, 200, 150)?;
+            let cropped_img = img.crop(40, 30, 200, 150)?;
+
+            assert_eq!(hasher.hash_source(&view)?, hasher.hash(&cropped_img)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hash_custom_image_source() -> anyhow::Result<()> {
+        /// Generates the pixels of a checkerboard on the fly
+        struct Checkerboard {
+            size : u32,
+            cell_size : u32
+        }
+
+        impl ImageSource for Checkerboard {
+            fn get_width(&self) -> u32 {
+                self.size
+            }
+
+            fn get_height(&self) -> u32 {
+                self.size
+            }
+
+            fn get_channels_per_pixel(&self) -> u8 {
+                1
+            }
+
+            fn get_pixel(&self, x : u32, y : u32) -> &[u8] {
+                if (x / self.cell_size + y / self.cell_size).is_multiple_of(2) { &[0] } else { &[255] }
+            }
+        }
+
+        let hasher = PerceptualHasher::new(test_config());
+        let checkerboard = Checkerboard { size : 64, cell_size : 16 };
+
+        let hash = hasher.hash_source(&checkerboard)?;
+
+        assert_eq!(hash, hasher.hash(&checkerboard.to_image()?)?);
+        assert_ne!(hash, ImageHash(0));
+        Ok(())
+    }
+
+    #[test]
+    fn do_not_split_image_into_empty_tiles() -> anyhow::Result<()> {
+        let image = Image::from_luma(&[0; 4 * 4], 4)?;
+
+        assert!(matches!(hash_tiles(&image, (0, 2), &test_config()), Err(ImgcmpError::InvalidDimensions(_))));
+        assert!(matches!(hash_tiles(&image, (2, 5), &test_config()), Err(ImgcmpError::InvalidDimensions(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn split_lengths_whose_products_overflow_u32_into_cells() {
+        let length = 3_000_000_000;
+
+        assert_eq!(cell_bounds(0, 3, length), (0, 1_000_000_000));
+        assert_eq!(cell_bounds(2, 3, length), (2_000_000_000, 3_000_000_000));
+        assert_eq!(cell_bounds(6, 7, u32::MAX), (3_681_400_538, u32::MAX));
+    }
+
+    #[test]
+    fn hash_small_image_with_configured_upscale_interpolation() -> anyhow::Result<()> {
+        let pixels : Vec<u8> = (0..8 * 8).map(|i| ((i % 8) * 30 + (i / 8) * 5) as u8).collect();
+        let image = Image::from_luma(&pixels, 8)?;
+        let nearest_config = Config { upscale_interpolation : Interpolation::Nearest, ..test_config() };
+
+        let bilinear_details = hash_image_details(&image, &test_config())?;
+        let nearest_details = hash_image_details(&image, &nearest_config)?;
+
+        assert_ne!(bilinear_details, nearest_details);
+        Ok(())
+    }
+
+    #[test]
+    fn detect_single_scene_cut_in_frame_sequence() -> anyhow::Result<()> {
+        // A gradient moving slowly, followed by a different scene
+        let frame = |shift : u32, pattern : &dyn Fn(u32, u32) -> u32| {
+            let pixels : Vec<u8> = (0..32 * 32).map(|i| (pattern(i % 32 + shift, i / 32) % 256) as u8).collect();
+            Image::from_luma(&pixels, 32)
+        };
+        let gradient = |x : u32, y : u32| x * 6 + y * 2;
+        let checkerboard = |x : u32, y : u32| if (x / 4 + y / 4).is_multiple_of(2) { 30 } else { 220 };
+        let frames = vec!(
+            frame(0, &gradient)?,
+            frame(1, &gradient)?,
+            frame(2, &gradient)?,
+            frame(0, &checkerboard)?,
+            frame(0, &checkerboard)?);
+
+        let cuts = scene_cuts(&frames, &test_config(), 10)?;
+
+        assert_eq!(cuts, [3]);
+        assert!(scene_cuts(&frames[..1], &test_config(), 10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn hash_selected_channel_of_image() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let green_channel = img.map(1, |pixel| vec!(pixel[1]))?;
+        let green_config = Config { grayscale_mode : GrayscaleMode::Channel(1), ..test_config() };
+
+        assert_eq!(hash_image_details(&img, &green_config)?, hash_image_details(&green_channel, &test_config())?);
+        assert_eq!(hash_image_details(&img, &Config { grayscale_mode : GrayscaleMode::Channel(5), ..test_config() }),
+                   Err(ImgcmpError::InvalidChannel { channel : 5, channels_per_pixel : 3 }));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_images_of_different_aspect_ratio_without_hashing() -> anyhow::Result<()> {
+        // Uniform images have the same hash regardless of their dimensions
+        let square_image = Image::from_luma(&[128; 100 * 100], 100)?;
+        let wide_image = Image::from_luma(&[128; 100 * 10], 100)?;
+        let almost_square_image = Image::from_luma(&[128; 90 * 95], 90)?;
+        let config = Config { max_aspect_ratio_diff : Some(0.2), ..test_config() };
+
+        assert!(compare_images(&square_image, &wide_image, test_config())?);
+        assert!(!compare_images(&square_image, &wide_image, config.clone())?);
+        assert!(compare_images(&square_image, &almost_square_image, config)?);
+        Ok(())
+    }
+
+    #[test]
+    fn return_error_for_hash_of_no_bits() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        let result = hash_image_details(&img, &Config { hash_bits : Some(0), ..test_config() });
+
+        assert!(matches!(result, Err(ImgcmpError::InvalidCoefficients(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn images_differing_only_in_masked_corner_have_same_hash() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg")?.resize_exact(64, 64, FilterType::Gaussian);
+        let image = to_image(img)?;
+        // Stamp a logo on the top left corner
+        let is_corner = |i : usize| i % 64 < 24 && i / 64 < 24;
+        let stamped_pixels : Vec<u8> = image.to_bytes().chunks(3).enumerate().
+            flat_map(|(i, pixel)| if is_corner(i) { vec!(0, 0, 0) } else { pixel.to_vec() }).
+            collect();
+        let stamped_image = Image::from(&stamped_pixels, 64, 3)?;
+        let mask : Vec<bool> = (0..64 * 64).map(is_corner).collect();
+
+        assert_ne!(hash_image_masked(&image, None, &test_config())?, hash_image_masked(&stamped_image, None, &test_config())?);
+        assert_eq!(hash_image_masked(&image, Some(&mask), &test_config())?,
+                   hash_image_masked(&stamped_image, Some(&mask), &test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_images_with_normalized_distance() -> anyhow::Result<()> {
+        let img1 = read_image("../assets/ferrari_roma.jpg").and_then(to_image)?;
+        let img2 = read_image("../assets/ferrari_roma_edited.png").and_then(to_image)?;
+        let normalized = DistanceMetric::NormalizedHamming { max_distance : 0.05 };
+        let hamming_hasher = PerceptualHasher::new(test_config());
+        let normalized_hasher = PerceptualHasher::new(Config { distance_metric : normalized, ..test_config() });
+        let small_normalized_hasher = PerceptualHasher::new(Config { distance_metric : normalized, dct_reduced_dimension : 4, ..test_config() });
+
+        let (hash1, hash2) = (hamming_hasher.hash(&img1)?, hamming_hasher.hash(&img2)?);
+        let hamming_distance = hamming_hasher.distance(&hash1, &hash2);
+
+        assert_eq!(hamming_distance, 2.0);
+        assert_eq!(normalized_hasher.distance(&hash1, &hash2), hamming_distance / 64.0);
+        let (small_hash1, small_hash2) = (small_normalized_hasher.hash(&img1)?, small_normalized_hasher.hash(&img2)?);
+        assert_eq!(small_normalized_hasher.distance(&small_hash1, &small_hash2), small_hash1.distance(&small_hash2) as f32 / 16.0);
+        assert!(!compare_images(&img1, &img2, test_config())?);
+        assert!(compare_images(&img1, &img2, Config { distance_metric : normalized, ..test_config() })?);
+        Ok(())
+    }
+
+    #[test]
+    fn apply_policy_for_images_below_minimum_size() -> anyhow::Result<()> {
+        let tiny_image = Image::from_luma(&[10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 130, 140, 150, 160], 4)?;
+        let warn_config = Config { min_source_size : Some(16), ..test_config() };
+        let reject_config = Config { small_image_policy : SmallImagePolicy::Reject, ..warn_config.clone() };
+
+        assert_eq!(hash_image_details(&tiny_image, &warn_config)?, hash_image_details(&tiny_image, &test_config())?);
+        assert_eq!(hash_image_details(&tiny_image, &reject_config),
+                   Err(ImgcmpError::ImageTooSmall { width : 4, height : 4, min_size : 16 }));
+        assert!(hash_image_details(&tiny_image, &Config { min_source_size : Some(4), ..reject_config }).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_equal_images_below_minimum_size() -> anyhow::Result<()> {
+        let tiny_image = Image::from_luma(&[10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 130, 140, 150, 160], 4)?;
+        let reject_config = Config { min_source_size : Some(16), small_image_policy : SmallImagePolicy::Reject, ..test_config() };
+        let too_small = Err(ImgcmpError::ImageTooSmall { width : 4, height : 4, min_size : 16 });
+
+        assert_eq!(compare_images(&tiny_image, &tiny_image.clone(), reject_config.clone()), too_small);
+        assert_eq!(compare_images(&tiny_image, &tiny_image.clone(), Config { dct_dimensions : vec!(16, 32), ..reject_config.clone() }),
+                   too_small);
+        let caching_comparator = CachingComparator::new(PerceptualHasher::new(reject_config), 4);
+        assert_eq!(compare_images_with(&tiny_image, &tiny_image.clone(), &caching_comparator), too_small);
+        assert!(compare_images(&tiny_image, &tiny_image.clone(), test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_heavily_compressed_image_using_low_frequencies_only() -> anyhow::Result<()> {
+        // cat.jpg keeps its hash even at the lowest quality, so a more sensitive image is used
+        let img = read_image("../assets/ferrari_roma2.jpg")?;
+        let mut buffer = Vec::new();
+        ::image::jpeg::JpegEncoder::new_with_quality(&mut buffer, 5).encode_image(&img)?;
+        let compressed_img = ::image::load_from_memory(&buffer)?;
+        let (image, compressed_image) = (to_image(img)?, to_image(compressed_img)?);
+
+        assert!(!compare_images(&image, &compressed_image, test_config())?);
+        assert!(compare_images(&image, &compressed_image, Config { low_freq_only : Some(4), ..test_config() })?);
+        Ok(())
+    }
+
+    #[test]
+    fn hash_images_with_mean_and_median_threshold() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let edited_img = read_image("../assets/cat_edited.jpg").and_then(to_image)?;
+        let different_img = read_image("../assets/cat2.jpg").and_then(to_image)?;
+
+        for threshold in [ThresholdStrategy::Mean, ThresholdStrategy::Median] {
+            let config = Config { threshold, allowed_distance : 3, ..test_config() };
+            let hasher = PerceptualHasher::new(config.clone());
+
+            assert_eq!(hasher.hash(&img)?, hasher.hash(&img.clone())?, "{:?}", threshold);
+            assert!(compare_images(&img, &edited_img, config.clone())?, "{:?}", threshold);
+            assert!(!compare_images(&img, &different_img, config)?, "{:?}", threshold);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn median_threshold_sets_half_of_the_bits() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        let hash = PerceptualHasher::new(Config { threshold : ThresholdStrategy::Median, ..test_config() }).hash(&img)?;
+
+        // Of the 63 AC coefficients, 31 are above their median, the middle one is equal to it
+        assert_eq!(hash.ones(), 31);
+        Ok(())
+    }
+
+    #[test]
+    fn transparent_image_composited_on_white_is_same_with_white_background_image() -> anyhow::Result<()> {
+        let sprite = read_image("../assets/cat.jpg")?.resize_exact(64, 64, FilterType::Triangle).to_rgba8();
+        let mut transparent_background = ::image::RgbaImage::new(128, 128);
+        let mut white_background = ::image::RgbaImage::from_pixel(128, 128, ::image::Rgba([255, 255, 255, 255]));
+        ::image::imageops::overlay(&mut transparent_background, &sprite, 32, 32);
+        ::image::imageops::overlay(&mut white_background, &sprite, 32, 32);
+        let transparent_image = to_image(DynamicImage::ImageRgba8(transparent_background))?;
+        let white_image = to_image(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(white_background).to_rgb8()))?;
+
+        assert!(!compare_images(&transparent_image, &white_image, test_config())?);
+        let config = Config { composite_background : Some([255, 255, 255]), ..test_config() };
+        assert!(compare_images(&transparent_image, &white_image, config)?);
+        Ok(())
+    }
+
+    #[test]
+    fn match_images_differing_in_semi_transparent_overlay_when_weighting_by_alpha() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg")?.to_rgba8();
+        // Every other pixel of the left third becomes a mostly transparent overlay pixel, next to opaque pixels of the cat
+        let with_overlay = |color : [u8; 3]| {
+            let mut overlaid_img = img.clone();
+            for (x, y, pixel) in overlaid_img.enumerate_pixels_mut() {
+                if x < img.width() / 3 && (x + y) % 2 == 0 {
+                    *pixel = ::image::Rgba([color[0], color[1], color[2], 8]);
+                }
+            }
+            to_image(DynamicImage::ImageRgba8(overlaid_img))
+        };
+        let opaque_img = to_image(DynamicImage::ImageRgba8(img.clone()))?;
+        let white_overlay_img = with_overlay([255, 255, 255])?;
+        let yellow_overlay_img = with_overlay([255, 220, 40])?;
+
+        assert!(!compare_images(&white_overlay_img, &opaque_img, test_config())?);
+        let config = Config { alpha_weighting : true, ..test_config() };
+        assert!(compare_images(&white_overlay_img, &opaque_img, config.clone())?);
+        assert!(compare_images(&white_overlay_img, &yellow_overlay_img, config)?);
+        Ok(())
+    }
+
+    #[test]
+    fn equalization_reduces_distance_of_low_contrast_image() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let mut low_contrast_img = img.clone();
+        // Squeezes the values into a narrow range, non linearly so that the DCT is affected
+        low_contrast_img.apply(|pixel| pixel.iter_mut().
+            for_each(|channel| *channel = (80.0 + 40.0 * (*channel as f32 / 255.0).powi(3)).round() as u8));
+
+        let distance = |config : Config| -> anyhow::Result<u32> {
+            let hasher = PerceptualHasher::new(config);
+            Ok(hasher.hash(&img)?.distance(&hasher.hash(&low_contrast_img)?))
+        };
+        let distance_without_equalization = distance(test_config())?;
+        let distance_with_equalization = distance(Config { equalize_histogram : true, ..test_config() })?;
+
+        assert!(distance_with_equalization < distance_without_equalization,
+                "{} >= {}", distance_with_equalization, distance_without_equalization);
+        Ok(())
+    }
+
+    #[test]
+    fn match_8_bit_image_with_its_16_bit_twin() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let promoted_channels : Vec<u16> = img.to_bytes().iter().map(|channel| *channel as u16 * 257).collect();
+        let promoted_img = Image::from_16bit(&promoted_channels, img.get_width(), img.get_channels_per_pixel())?;
+        let hasher = PerceptualHasher::new(test_config());
+
+        assert_eq!(hasher.hash(&img)?.distance(&hasher.hash(&promoted_img)?), 0);
+        assert!(compare_images(&img, &promoted_img, test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn similar_images_land_in_same_bucket_more_often_than_dissimilar_ones() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+        // Pairs of an image and its edited version
+        let paths = [
+            ("../assets/cat.jpg", "../assets/cat_edited.jpg"),
+            ("../assets/cat2.jpg", "../assets/cat2_edited.jpg"),
+            ("../assets/ferrari_roma.jpg", "../assets/ferrari_roma_edited.png"),
+            ("../assets/ferrari_roma2.jpg", "../assets/ferrari_roma2_edited.jpg")];
+        let bucket = |path : &str| -> anyhow::Result<u64> {
+            let img = read_image(path).and_then(to_image)?;
+            // Buckets of the 8 most significant bits of the keys
+            Ok(hasher.bucket_key(&hasher.hash(&img)?) >> 56)
+        };
+        let buckets = paths.iter().
+            map(|(original, edited)| Ok((bucket(original)?, bucket(edited)?))).
+            collect::<anyhow::Result<Vec<(u64, u64)>>>()?;
+
+        let similar_pairs_in_same_bucket = buckets.iter().filter(|(original, edited)| original == edited).count();
+        let mut dissimilar_pairs = 0;
+        let mut dissimilar_pairs_in_same_bucket = 0;
+        for (i, left) in buckets.iter().enumerate() {
+            for right in &buckets[i + 1..] {
+                for (left_bucket, right_bucket) in [(left.0, right.0), (left.0, right.1), (left.1, right.0), (left.1, right.1)] {
+                    dissimilar_pairs += 1;
+                    dissimilar_pairs_in_same_bucket += (left_bucket == right_bucket) as usize;
+                }
+            }
+        }
+
+        assert!(similar_pairs_in_same_bucket as f32 / buckets.len() as f32 >
+                dissimilar_pairs_in_same_bucket as f32 / dissimilar_pairs as f32,
+                "{}/{} similar and {}/{} dissimilar pairs in the same bucket", similar_pairs_in_same_bucket, buckets.len(),
+                dissimilar_pairs_in_same_bucket, dissimilar_pairs);
+        Ok(())
+    }
+
+    #[test]
+    fn put_lowest_frequencies_in_most_significant_bits_of_bucket_key() {
+        let hasher = PerceptualHasher::new(test_config());
+
+        // Bits 1 and 8 are the coefficients of the (1, 0) and (0, 1) frequencies, bit 63 the one of (7, 7)
+        assert_eq!(hasher.bucket_key(&ImageHash(1 << 1)), 1 << 62);
+        assert_eq!(hasher.bucket_key(&ImageHash(1 << 8)), 1 << 61);
+        assert_eq!(hasher.bucket_key(&ImageHash(1 << 63)), 1);
+    }
+
+    #[test]
+    fn detect_solid_colors_as_low_information() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+        let red_image = Image::from_rgb(&[(255, 0, 0); 64 * 64], 64)?;
+        let blue_image = Image::from_rgb(&[(0, 0, 255); 64 * 64], 64)?;
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        assert_eq!(hasher.hash(&red_image)?, hasher.hash(&blue_image)?);
+        assert!(hasher.is_low_information(&red_image));
+        assert!(hasher.is_low_information(&blue_image));
+        assert!(!hasher.is_low_information(&img));
+        Ok(())
+    }
+
+    #[test]
+    fn match_image_with_solid_border_when_trimming_borders() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let bordered_img = img.pad(5, 5, 5, 5, &[0, 0, 0])?;
+        let hasher = PerceptualHasher::new(test_config());
+        let trimming_hasher = PerceptualHasher::new(Config { trim_border : Some(0), ..test_config() });
+
+        assert_eq!(bordered_img.trim_border(0), img);
+        assert_eq!(trimming_hasher.hash(&bordered_img)?, hasher.hash(&img)?);
+        assert_ne!(hasher.hash(&bordered_img)?, hasher.hash(&img)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn hash_is_stable_across_re_encoding() -> anyhow::Result<()> {
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+        let median_config = Config { threshold : ThresholdStrategy::Median, ..Config::default() };
+
+        for config in [Config::default(), median_config] {
+            let distances = testing::verify_hash_stability(&img, &config, 1);
+
+            assert_eq!(distances.len(), 1 + testing::JPEG_QUALITIES.len());
+            assert_eq!(distances[0], 0, "{:?}", config.threshold);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn hash_image_file() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+        let img = read_image("../assets/cat.jpg").and_then(to_image)?;
+
+        assert_eq!(hasher.hash_file("../assets/cat.jpg")?, hasher.hash(&img)?);
+        assert!(matches!(hasher.hash_file("../assets/missing.jpg"), Err(ImgcmpError::Io(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn match_images_differing_only_by_exif_orientation() -> anyhow::Result<()> {
+        let hasher = PerceptualHasher::new(test_config());
+
+        assert_eq!(hasher.hash_file("../assets/cat_exif_rotated.jpg")?, hasher.hash_file("../assets/cat_exif_upright.jpg")?);
+        Ok(())
+    }
+
+    #[test]
+    fn compare_rgba_buffers() -> anyhow::Result<()> {
+        let gradient = |x : u32, y : u32| vec!((x * 8) as u8, (y * 8) as u8, 100, 255);
+        let checkerboard = |x : u32, y : u32| {
+            let value = if (x / 4 + y / 4).is_multiple_of(2) { 0 } else { 255 };
+            vec!(value, value, value, 255)
+        };
+        let rgba_buffer = |f : &dyn Fn(u32, u32) -> Vec<u8>| {
+            (0..32).flat_map(|y| (0..32).flat_map(move |x| f(x, y))).collect::<Vec<u8>>()
+        };
+        let gradient_buffer = rgba_buffer(&gradient);
+        let checkerboard_buffer = rgba_buffer(&checkerboard);
+
+        assert!(compare_rgba(&gradient_buffer, &gradient_buffer.clone(), 32, 32, &test_config())?);
+        assert!(!compare_rgba(&gradient_buffer, &checkerboard_buffer, 32, 32, &test_config())?);
+        Ok(())
+    }
+
+    #[test]
+    fn do_not_compare_rgba_buffers_of_wrong_size() -> anyhow::Result<()> {
+        let buffer = vec!(0; 32 * 32 * 4);
+
+        assert_eq!(compare_rgba(&buffer, &buffer[1..], 32, 32, &test_config()),
+                   Err(ImgcmpError::BufferLengthMismatch { expected : 4096, actual : 4095 }));
+        assert_eq!(compare_rgba(&buffer, &buffer, 32, 16, &test_config()),
+                   Err(ImgcmpError::BufferLengthMismatch { expected : 2048, actual : 4096 }));
         Ok(())
     }
 
@@ -180,10 +1830,10 @@ mod tests {
     fn to_image(decoded_image : DynamicImage) -> anyhow::Result<Image> {
         let width = decoded_image.width();
         let channel_count = decoded_image.color().channel_count();
-        Image::from(&decoded_image.into_bytes(),width, channel_count)
+        Ok(Image::from(&decoded_image.into_bytes(),width, channel_count)?)
     }
 
     fn test_config() -> Config {
-        Config { dct_dimension : 32, dct_reduced_dimension : 8, allowed_distance : 0 }
+        Config { allowed_distance : 0, ..Config::default() }
     }
-}
\ No newline at end of file
+}
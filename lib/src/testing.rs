@@ -0,0 +1,30 @@
+//! Helpers for testing the robustness of hashes in downstream crates.
+
+use crate::{Config, Image, PerceptualHasher};
+use ::image::ImageOutputFormat;
+
+/// Qualities at which images are re-encoded as JPEG by `verify_hash_stability`
+pub const JPEG_QUALITIES : [u8; 4] = [95, 85, 75, 50];
+
+/// Hashes the passed image, re-encodes it as PNG and as JPEG at each of `JPEG_QUALITIES`, decodes it again
+/// and hashes it again. Panics when the Hamming distance of any JPEG hash to the original hash exceeds
+/// `max_distance`, or when the PNG hash differs at all, since PNG is lossless, mentioning the encoding
+/// and the distance. Returns the distances, PNG first.
+pub fn verify_hash_stability(image : &Image, config : &Config, max_distance : u32) -> Vec<u32> {
+    let hasher = PerceptualHasher::new(config.clone());
+    let hash = hasher.hash(image).expect("the original image can be hashed");
+    let dynamic_image = image.to_dynamic_image().expect("the original image can be encoded");
+
+    let formats = std::iter::once(("PNG".to_string(), ImageOutputFormat::Png)).
+        chain(JPEG_QUALITIES.iter().map(|quality| (format!("JPEG at quality {}", quality), ImageOutputFormat::Jpeg(*quality))));
+    formats.enumerate().map(|(index, (name, format))| {
+        let mut bytes = Vec::new();
+        dynamic_image.write_to(&mut bytes, format).unwrap_or_else(|error| panic!("encoding as {} failed: {}", name, error));
+        let decoded_image = Image::from_encoded(&bytes).unwrap_or_else(|error| panic!("decoding {} failed: {}", name, error));
+        let distance = hash.distance(&hasher.hash(&decoded_image).expect("the re-encoded image can be hashed"));
+        let max_distance = if index == 0 { 0 } else { max_distance };
+        assert!(distance <= max_distance, "hash of image re-encoded as {} is at distance {}, more than {}",
+                name, distance, max_distance);
+        distance
+    }).collect()
+}